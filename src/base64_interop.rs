@@ -0,0 +1,118 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use crate::{Bitmap, DecodeError};
+
+/// Length of the little endian `num_bits` prefix
+const HEADER_LEN: usize = 8;
+
+impl Bitmap {
+    /// Encodes the bitmap as URL safe base64 without padding.
+    ///
+    /// The encoded bytes are `num_bits` as a little endian `u64` followed by the
+    ///  `num_bits.div_ceil(8)` bytes of the bitmap, so the output can be embedded in JSON or URLs.
+    pub fn to_base64(&self) -> String {
+        let num_bits = self.num_bits();
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + self.num_bytes());
+        bytes.extend_from_slice(&(num_bits as u64).to_le_bytes());
+        bytes.extend_from_slice(self.as_byte_slice());
+        if let Some(last) = bytes.last_mut().filter(|_| !num_bits.is_multiple_of(8)) {
+            *last &= (1 << (num_bits % 8)) - 1;
+        }
+
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    /// Decodes a bitmap written by [Self::to_base64].
+    ///
+    /// Bits past `num_bits` in the last byte are ignored and zeroed in the returned bitmap.
+    pub fn from_base64(s: &str) -> Result<Bitmap, DecodeError> {
+        let bytes = URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| DecodeError::InvalidBase64)?;
+
+        if bytes.len() < HEADER_LEN {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let num_bits = u64::from_le_bytes(bytes[..HEADER_LEN].try_into().unwrap());
+        let num_bits = usize::try_from(num_bits).map_err(|_| DecodeError::TooLarge)?;
+        let body = &bytes[HEADER_LEN..];
+
+        let num_bytes = num_bits.div_ceil(8);
+        if body.len() < num_bytes {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        if body.len() > num_bytes {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        let mut bitmap = Bitmap::new(num_bits);
+        bitmap.as_mut_byte_slice().copy_from_slice(body);
+        bitmap.clear_padding_bits();
+
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        for bitmap in [
+            Bitmap::new(0),
+            Bitmap::ones(13),
+            Bitmap::from_fn(1001, |i| (i * 7919) % 13 < 6),
+            Bitmap::ones(200).slice(0, 70),
+        ] {
+            let encoded = bitmap.to_base64();
+            assert!(encoded
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+
+            let decoded = Bitmap::from_base64(&encoded).unwrap();
+            assert_eq!(decoded, bitmap);
+            assert_eq!(decoded.validate(), Ok(()));
+        }
+
+        // dirty bits of the last byte are dropped
+        let mut bytes = 3u64.to_le_bytes().to_vec();
+        bytes.push(0xff);
+        let decoded = Bitmap::from_base64(&URL_SAFE_NO_PAD.encode(bytes)).unwrap();
+        assert_eq!(decoded, Bitmap::ones(3));
+        assert_eq!(decoded.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let encoded = Bitmap::ones(100).to_base64();
+
+        assert_eq!(
+            Bitmap::from_base64("not base64!"),
+            Err(DecodeError::InvalidBase64)
+        );
+        assert_eq!(
+            Bitmap::from_base64(&format!("{}=", encoded)),
+            Err(DecodeError::InvalidBase64)
+        );
+        assert_eq!(
+            Bitmap::from_base64(&URL_SAFE_NO_PAD.encode([1, 2, 3])),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        let mut short = 100u64.to_le_bytes().to_vec();
+        short.extend_from_slice(&[0xff; 12]);
+        assert_eq!(
+            Bitmap::from_base64(&URL_SAFE_NO_PAD.encode(&short)),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        short.extend_from_slice(&[0xff; 2]);
+        assert_eq!(
+            Bitmap::from_base64(&URL_SAFE_NO_PAD.encode(&short)),
+            Err(DecodeError::TrailingBytes)
+        );
+    }
+}
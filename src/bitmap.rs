@@ -1,32 +1,264 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
 use std::sync::Arc;
 
 use arrs_buffer::Buffer;
 
+use crate::{BitmapRef, BuildError, OutOfBounds, PrefixPopcount, ValidationError};
+
 /// An uncompressed bitmap implementation, optimized for dense bitmaps.
+///
+/// Bits are stored in an [arrs_buffer::Buffer], which is aligned to and padded to [arrs_buffer::ALIGNMENT]
+///  bytes. So the whole word holding the last bit can always be read, as long as the buffer holds
+///  at least `num_bits.div_ceil(8)` bytes.
 #[derive(Clone)]
 pub struct Bitmap {
     buf: Arc<Buffer>,
     num_bits: usize,
 }
 
+/// Upper bound on the number of bits of a bitmap, so bit counts always fit in an `isize` like the
+///  lengths of Rust slices.
+pub(crate) const MAX_BITS: usize = isize::MAX as usize;
+
+// Words of the bitmap are read directly from the buffer so it has to be aligned to u64.
+const _: () = assert!(arrs_buffer::ALIGNMENT.is_multiple_of(std::mem::align_of::<u64>()));
+
 impl Bitmap {
+    /// Create a bitmap with all bits unset
+    ///
+    /// # Panics
+    ///
+    /// Panics with a "bitmap too large" message if `num_bits > isize::MAX`, or if the memory
+    ///  can't be allocated.
+    pub fn new(num_bits: usize) -> Self {
+        assert_num_bits(num_bits);
+        let num_bytes = num_bits.div_ceil(8);
+
+        let bitmap = Self {
+            buf: Arc::new(Buffer::new(num_bytes)),
+            num_bits,
+        };
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Same as [Self::new] but returns an error instead of panicking if `num_bits > isize::MAX`.
+    ///
+    /// Still panics if the memory can't be allocated.
+    pub(crate) fn try_new(num_bits: usize) -> Result<Self, BuildError> {
+        Ok(Self {
+            buf: Arc::new(try_new_buffer(num_bits)?),
+            num_bits,
+        })
+    }
+
+    /// Create a bitmap with all bits set
+    pub fn ones(num_bits: usize) -> Self {
+        Self::from_word_fn(num_bits, |_| u64::MAX)
+    }
+
+    /// Create a bitmap where the word at index `i` is `f(i)`.
+    ///
+    /// `f` is called in ascending word index order, bits of the last word that are past `num_bits`
+    ///  are masked out.
+    pub fn from_word_fn<F: FnMut(usize) -> u64>(num_bits: usize, mut f: F) -> Self {
+        let mut bitmap = Self::new(num_bits);
+
+        for (word_index, dst) in bitmap.words_mut().iter_mut().enumerate() {
+            *dst = f(word_index).to_le();
+        }
+        bitmap.mask_last_word();
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
     /// Create a bitmap from buffer
     ///
     /// # Panics
     ///
     /// Panics if given buffer can't hold the given number of bits.
     pub fn from_buf(buf: Arc<Buffer>, num_bits: usize) -> Self {
-        let num_bytes = num_bits.checked_next_multiple_of(8).unwrap() / 8;
+        let num_bytes = num_bits.div_ceil(8);
         assert!(num_bytes <= buf.len());
 
         Self { buf, num_bits }
     }
 
+    /// Create a bitmap from buffer without checking if the buffer can hold the given number of bits.
+    ///
+    /// # Safety
+    ///
+    /// `buf.len()` should be at least `num_bits.div_ceil(8)`. [Self::validate] can be used to check
+    ///  a bitmap created with this function.
+    pub unsafe fn from_buf_unchecked(buf: Arc<Buffer>, num_bits: usize) -> Self {
+        Self { buf, num_bits }
+    }
+
+    /// Checks the structural invariants of the bitmap.
+    ///
+    /// The buffer has to be big enough to hold `num_bits` and all bits of the buffer that are past
+    ///  `num_bits` have to be zero.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let num_bytes = self.num_bits.div_ceil(8);
+        if num_bytes > self.buf.len() {
+            return Err(ValidationError::BufferTooSmall {
+                num_bits: self.num_bits,
+                buffer_len: self.buf.len(),
+            });
+        }
+
+        let mut num_padding_bits = 0;
+        if let Some(&last) = self.as_word_slice().last() {
+            num_padding_bits += (u64::from_le(last) & !self.last_word_mask()).count_ones() as usize;
+        }
+        if let Some(tail) = self.buf.as_slice().get(self.num_words() * 8..) {
+            num_padding_bits += tail.iter().map(|b| b.count_ones() as usize).sum::<usize>();
+        }
+
+        if num_padding_bits > 0 {
+            return Err(ValidationError::DirtyPadding { num_padding_bits });
+        }
+
+        Ok(())
+    }
+
+    /// Create a bitmap with all bits unset, reusing the given buffer.
+    ///
+    /// Zeroes the buffer before use. Copies the buffer if it is shared with something else.
+    ///  Can be used with [Self::into_buffer] to pool buffers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given buffer can't hold the given number of bits.
+    pub fn from_buf_cleared(mut buf: Arc<Buffer>, num_bits: usize) -> Self {
+        let num_bytes = num_bits.div_ceil(8);
+        assert!(num_bytes <= buf.len());
+
+        Arc::make_mut(&mut buf).as_mut_slice().fill(0);
+
+        let bitmap = Self { buf, num_bits };
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Copies `self` into `dst`, reusing the buffer of `dst` if it isn't shared and is big enough.
+    ///
+    /// Otherwise `dst` is set to a clone of `self`, sharing the buffer of `self`.
+    pub fn clone_into(&self, dst: &mut Bitmap) {
+        let num_bytes = self.num_bits.div_ceil(8);
+
+        match Arc::get_mut(&mut dst.buf) {
+            Some(buf) if buf.len() >= num_bytes => {
+                buf.as_mut_slice()[..num_bytes].copy_from_slice(self.as_byte_slice());
+                dst.num_bits = self.num_bits;
+                dst.clear_padding_bits();
+            }
+            _ => *dst = self.clone(),
+        }
+    }
+
+    /// Returns the underlying buffer if it is not shared with another bitmap.
+    pub fn into_buffer(mut self) -> Option<Arc<Buffer>> {
+        match Arc::get_mut(&mut self.buf) {
+            Some(_) => Some(self.buf),
+            None => None,
+        }
+    }
+
+    /// Returns the owned underlying buffer if it is not shared with another bitmap, gives the
+    ///  bitmap back otherwise.
+    ///
+    /// The buffer might be longer than `self.num_bits().div_ceil(8)` bytes.
+    pub fn try_into_buffer(self) -> Result<Buffer, Bitmap> {
+        let num_bits = self.num_bits;
+        Arc::try_unwrap(self.buf).map_err(|buf| Bitmap { buf, num_bits })
+    }
+
     /// Number of bits in this bitmap
     pub fn num_bits(&self) -> usize {
         self.num_bits
     }
 
+    /// Number of bytes needed to hold the bits, `self.num_bits().div_ceil(8)`
+    pub fn num_bytes(&self) -> usize {
+        self.num_bits.div_ceil(8)
+    }
+
+    /// Length of the underlying buffer in bytes
+    pub fn capacity_bytes(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Makes the length of the underlying buffer a multiple of 8 bytes and zeroes all bits past
+    ///  `num_bits`, so consumers can read `self.num_bits().div_ceil(64)` whole words from it.
+    ///
+    /// Doesn't change `num_bits`. Reallocates if the buffer length isn't a multiple of 8 bytes and
+    ///  copies the buffer if it is shared with another bitmap.
+    pub fn pad_to_word_boundary(&mut self) {
+        self.reserve_bytes(self.buf.len().next_multiple_of(8));
+        self.clear_padding_bits();
+    }
+
+    /// Returns if length of the underlying buffer is a multiple of `byte_boundary`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `byte_boundary` is zero.
+    pub fn is_aligned_to(&self, byte_boundary: usize) -> bool {
+        self.capacity_bytes().is_multiple_of(byte_boundary)
+    }
+
+    /// Grows the underlying buffer so its length is a multiple of `byte_boundary` bytes.
+    ///  The new bytes are zeroed, `num_bits` doesn't change.
+    ///
+    /// Allocates a new buffer unless it is already aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `byte_boundary` is zero or if the aligned length overflows.
+    pub fn align_to(&mut self, byte_boundary: usize) {
+        if self.is_aligned_to(byte_boundary) {
+            return;
+        }
+
+        let len = self
+            .capacity_bytes()
+            .checked_next_multiple_of(byte_boundary)
+            .unwrap();
+        let num_bytes = self.num_bits.div_ceil(8);
+
+        let mut buf = Buffer::new(len);
+        buf.as_mut_slice()[..num_bytes].copy_from_slice(&self.buf.as_slice()[..num_bytes]);
+
+        self.buf = Arc::new(buf);
+        self.mask_last_word();
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+    }
+
+    /// Returns the number of leading bytes of [Self::as_byte_slice] before the first byte whose
+    /// address is a multiple of `byte_boundary`, capped at [Self::num_bytes].
+    ///
+    /// The buffer is allocated with [arrs_buffer::ALIGNMENT] alignment, so this is zero for
+    /// boundaries up to that.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `byte_boundary` is not a power of two.
+    pub fn simd_align_prefix(&self, byte_boundary: usize) -> usize {
+        let bytes = self.as_byte_slice();
+        bytes.as_ptr().align_offset(byte_boundary).min(bytes.len())
+    }
+
     /// Slices the bitmap with given range.
     ///
     /// Beware! If start_bit != 0, this will allocate a new Buffer and move the whole sliced area to that buffer.
@@ -37,6 +269,10 @@ impl Bitmap {
     pub fn slice(&self, start_bit: usize, num_bits: usize) -> Self {
         assert!(start_bit.checked_add(num_bits).unwrap() <= self.num_bits);
 
+        if num_bits == 0 {
+            return Self::new(0);
+        }
+
         if start_bit == 0 {
             return Self {
                 buf: self.buf.clone(),
@@ -44,147 +280,5925 @@ impl Bitmap {
             };
         }
 
-        let num_bytes = num_bits.checked_next_multiple_of(8).unwrap() / 8;
+        let num_bytes = num_bits.div_ceil(8);
 
         let mut buf = Buffer::new(num_bytes);
 
         let start_word = start_bit / 64;
         let shift = start_bit % 64;
 
+        // The source range can span one more word than the destination. Only re-align as many
+        //  words as the destination has so neither buffer is accessed out of bounds, then merge
+        //  the bits of the extra source word into the last destination word.
+        let dst_words = num_bits.div_ceil(64);
+        let src_words = (start_bit + num_bits).div_ceil(64) - start_word;
+
         unsafe {
-            crate::compute::re_align(
-                (self.buf.as_ptr() as *const u64).add(start_word),
-                buf.as_mut_ptr() as *mut u64,
-                ((num_bits + 63) / 64) + 1,
-                shift as u32,
-            );
+            let src = (self.buf.as_ptr() as *const u64).add(start_word);
+            let dst = buf.as_mut_ptr() as *mut u64;
+
+            crate::compute::re_align(src, dst, dst_words, shift as u32);
+
+            if src_words > dst_words {
+                let last = dst.add(dst_words - 1);
+                let extra = u64::from_le(*src.add(dst_words));
+                *last = (u64::from_le(*last) | extra << (64 - shift)).to_le();
+            }
         };
 
-        Self {
+        let mut bitmap = Self {
             buf: Arc::new(buf),
             num_bits,
+        };
+        bitmap.mask_last_word();
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Same as [Self::slice] but the result always owns a buffer of exactly
+    ///  `num_bits.div_ceil(8)` bytes with clean padding.
+    ///
+    /// Unlike [Self::slice], a range starting at zero is copied instead of sharing the buffer,
+    ///  so a small region kept around doesn't keep a large buffer alive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    pub fn extract(&self, start_bit: usize, num_bits: usize) -> Self {
+        assert!(start_bit.checked_add(num_bits).unwrap() <= self.num_bits);
+
+        if start_bit != 0 {
+            return self.slice(start_bit, num_bits);
         }
+
+        let mut bitmap = Self::new(num_bits);
+        let num_bytes = bitmap.num_bytes();
+        bitmap
+            .as_mut_byte_slice()
+            .copy_from_slice(&self.buf.as_slice()[..num_bytes]);
+        bitmap.clear_padding_bits();
+
+        bitmap
     }
 
-    /// Returns the set of set bit ranges in the bitmap
-    pub fn set_ranges(&self) -> Vec<(usize, usize)> {
-        if self.num_bits == 0 {
-            return Vec::new();
+    /// Returns the canonical form of the bitmap, a buffer of exactly [Self::num_bytes] bytes with
+    /// all bits past `num_bits` zeroed.
+    ///
+    /// Value-equal bitmaps have byte-identical normalized buffers. Shares the buffer if it is
+    /// already canonical, copies it otherwise.
+    pub fn normalize(&self) -> Self {
+        if self.capacity_bytes() == self.num_bytes() && self.validate().is_ok() {
+            return self.clone();
         }
 
-        let len = self.buf.len().next_multiple_of(64);
+        self.extract(0, self.num_bits)
+    }
+
+    /// Returns a read-only view of `num_bits` bits starting at `start_bit`, without allocating.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start_bit + num_bits > self.num_bits()`.
+    pub fn view(&self, start_bit: usize, num_bits: usize) -> BitmapRef<'_> {
+        assert!(start_bit.checked_add(num_bits).unwrap() <= self.num_bits);
 
-        unsafe { crate::compute::set_ranges(self.buf.as_ptr(), len) }
+        BitmapRef::new(&self.buf, start_bit, num_bits)
     }
 
-    pub fn from_bools(bools: &[bool]) -> Self {
-        let num_bits = bools.len();
-        let num_bytes = num_bits.checked_next_multiple_of(8).unwrap() / 8;
+    /// Same as [Self::slice] but clamps the range to the bitmap instead of panicking.
+    ///
+    /// Returns an empty bitmap if `start_bit >= self.num_bits()`.
+    pub fn saturating_slice(&self, start_bit: usize, num_bits: usize) -> Self {
+        let start_bit = start_bit.min(self.num_bits);
+        let num_bits = num_bits.min(self.num_bits - start_bit);
 
-        let mut buf = Buffer::new(num_bytes);
+        self.slice(start_bit, num_bits)
+    }
 
-        // Compiler vectorizes this
-        unsafe {
-            let mut bools_ptr = bools.as_ptr() as *const u8;
-            let mut buf_ptr = buf.as_mut_ptr();
-            for _ in 0..num_bits / 8 {
-                let byte = *bools_ptr
-                    | *bools_ptr.add(1) << 1
-                    | *bools_ptr.add(2) << 2
-                    | *bools_ptr.add(3) << 3
-                    | *bools_ptr.add(4) << 4
-                    | *bools_ptr.add(5) << 5
-                    | *bools_ptr.add(6) << 6
-                    | *bools_ptr.add(7) << 7;
+    /// Iterates over consecutive, non-overlapping sub-bitmaps of `chunk_bits` bits.
+    ///  The last chunk is shorter if `chunk_bits` doesn't divide `self.num_bits()`.
+    ///
+    /// Each chunk is created with [Self::slice].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_bits` is zero.
+    pub fn chunks(&self, chunk_bits: usize) -> impl Iterator<Item = Bitmap> + '_ {
+        assert!(chunk_bits > 0);
+
+        (0..self.num_bits)
+            .step_by(chunk_bits)
+            .map(move |start| self.slice(start, chunk_bits.min(self.num_bits - start)))
+    }
+
+    /// Splits the bitmap into consecutive segments of `n` bits, the last one is shorter if `n`
+    ///  doesn't divide `self.num_bits()`.
+    ///
+    /// If `n` is a multiple of 8 every segment starts at a byte boundary, so the bytes are copied
+    ///  directly instead of being re-aligned like [Self::chunks] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn split_every(&self, n: usize) -> Vec<Bitmap> {
+        assert!(n > 0);
+
+        if !n.is_multiple_of(8) {
+            return self.chunks(n).collect();
+        }
+
+        let bytes = self.as_byte_slice();
+        (0..self.num_bits)
+            .step_by(n)
+            .map(|start| {
+                Bitmap::from_packed_with_offset(bytes, start, n.min(self.num_bits - start))
+            })
+            .collect()
+    }
+
+    /// Iterates over all overlapping sub-bitmaps of `window_bits` bits, advancing one bit at a time.
+    ///  Yields nothing if `window_bits > self.num_bits()`.
+    ///
+    /// Each window is created with [Self::slice].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_bits` is zero.
+    pub fn windows(&self, window_bits: usize) -> impl Iterator<Item = Bitmap> + '_ {
+        assert!(window_bits > 0);
+
+        let num_windows = self.num_bits.checked_sub(window_bits).map_or(0, |n| n + 1);
+
+        (0..num_windows).map(move |start| self.slice(start, window_bits))
+    }
 
-                *buf_ptr = byte;
+    /// Iterates over all maximal runs of equal bits as `(value, start, len)`.
+    ///
+    /// Runs alternate in value and cover `[0, self.num_bits())` exactly.
+    pub fn iter_runs(&self) -> impl Iterator<Item = (bool, usize, usize)> + '_ {
+        let mut pos = 0;
 
-                bools_ptr = bools_ptr.add(8);
-                buf_ptr = buf_ptr.add(1);
+        std::iter::from_fn(move || {
+            if pos >= self.num_bits {
+                return None;
             }
 
-            if num_bits % 8 > 0 {
-                let mut byte = 0;
-                for (shift, _) in (0..num_bits % 8).enumerate() {
-                    byte |= *bools_ptr << shift;
-                    bools_ptr = bools_ptr.add(1);
-                }
-                *buf_ptr = byte;
+            let value = unsafe { self.get_unchecked(pos) };
+            let end = self.next_with_value(pos, !value);
+            let run = (value, pos, end - pos);
+            pos = end;
+
+            Some(run)
+        })
+    }
+
+    /// Returns the lengths of alternating runs, starting with a run of unset bits.
+    ///
+    /// The first length is zero if the bitmap starts with a set bit, all other lengths are
+    ///  non-zero. An empty bitmap has no runs.
+    pub fn to_run_lengths(&self) -> Vec<usize> {
+        let mut lengths = Vec::new();
+        for (value, _, len) in self.iter_runs() {
+            if lengths.is_empty() && value {
+                lengths.push(0);
             }
+            lengths.push(len);
         }
+        lengths
+    }
 
-        Self {
-            buf: Arc::new(buf),
-            num_bits,
+    /// Collects [Self::iter_runs] into a `Vec` of `(value, start, len)`.
+    pub fn to_run_pairs(&self) -> Vec<(bool, usize, usize)> {
+        self.iter_runs().collect()
+    }
+
+    /// Number of indices `i` where the bits at `i - 1` and `i` differ.
+    ///
+    /// This is the number of runs minus one for a non-empty bitmap.
+    pub fn count_transitions(&self) -> usize {
+        let num_words = self.num_words();
+        let mut count = 0;
+        let mut carry = 0;
+
+        for (word_index, word) in self.words().enumerate() {
+            let mut diff = word ^ ((word << 1) | carry);
+            if word_index == 0 {
+                diff &= !1;
+            }
+            if word_index + 1 == num_words {
+                diff &= self.last_word_mask();
+            }
+
+            count += diff.count_ones() as usize;
+            carry = word >> 63;
         }
+
+        count
     }
 
-    #[inline(always)]
-    /// Returns a shared pointer to the underlying buffer
-    pub fn buf(&self) -> Arc<Buffer> {
-        self.buf.clone()
+    /// Iterates over the indices of set bits in ascending order
+    pub fn iter_set_bits(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words()
+            .enumerate()
+            .flat_map(|(word_index, word)| set_bit_positions(word, word_index * 64))
     }
 
-    #[inline(always)]
-    pub fn get(&self, bit_index: usize) -> Option<bool> {
-        if bit_index >= self.num_bits {
-            return None;
-        }
+    /// Calls `f` with the position of every set bit, splitting the words across the rayon thread pool.
+    ///
+    /// Positions are visited in no particular order.
+    #[cfg(feature = "rayon")]
+    pub fn par_for_each_set_bit<F: Fn(usize) + Sync>(&self, f: F) {
+        use rayon::prelude::*;
 
-        Some(unsafe { self.get_unchecked(bit_index) })
+        (0..self.num_words())
+            .into_par_iter()
+            // Splitting below a cache line worth of words isn't worth it
+            .with_min_len(8)
+            .for_each(|word_index| {
+                for index in set_bit_positions(self.word(word_index), word_index * 64) {
+                    f(index);
+                }
+            });
     }
 
-    /// # Safety
+    /// Iterates over the indices of set bits in `[start, start + len)` in ascending order
     ///
-    /// `bit_index` should be less than `self.num_bits()`
-    #[inline(always)]
-    pub unsafe fn get_unchecked(&self, bit_index: usize) -> bool {
-        let byte_index = bit_index / 8;
-        let mask = 1 << (bit_index % 8);
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    pub fn iter_set_bits_in_range(
+        &self,
+        start: usize,
+        len: usize,
+    ) -> impl Iterator<Item = usize> + '_ {
+        self.range_words(start, len)
+            .flat_map(|(word_index, word)| set_bit_positions(word, word_index * 64))
+    }
 
-        unsafe { (*self.buf.as_ptr().add(byte_index) & mask) != 0 }
+    /// Returns the indices of set bits in ascending order
+    pub fn to_indices(&self) -> Vec<usize> {
+        self.iter_set_bits().collect()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use rand::{Rng, SeedableRng};
-    use rand_chacha::ChaCha8Rng;
+    /// Splits the set bit indices into containers of 2^16 bits like roaring bitmaps do.
+    ///
+    /// Each container is `(high, lows)` where `high` is the upper 16 bits of the indices in it
+    ///  and `lows` are the sorted lower 16 bits. Only containers with set bits are returned, in
+    ///  ascending order of `high`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a set bit index doesn't fit in a `u32`.
+    pub fn to_containers(&self) -> Vec<(u16, Vec<u16>)> {
+        let mut containers: Vec<(u16, Vec<u16>)> = Vec::new();
 
-    use super::*;
+        for index in self.iter_set_bits() {
+            let index = u32::try_from(index).expect("set bit index doesn't fit in a container");
+            let (high, low) = ((index >> 16) as u16, index as u16);
 
-    fn run_test(bools: &[bool]) {
-        let bitmap = Bitmap::from_bools(bools);
+            match containers.last_mut() {
+                Some((last_high, lows)) if *last_high == high => lows.push(low),
+                _ => containers.push((high, vec![low])),
+            }
+        }
+
+        containers
+    }
+
+    /// Index of the first set bit
+    pub fn find_first_set(&self) -> Option<usize> {
+        let index = self.next_with_value(0, true);
+        (index < self.num_bits).then_some(index)
+    }
+
+    /// Index of the last set bit
+    pub fn find_last_set(&self) -> Option<usize> {
+        (0..self.num_words()).rev().find_map(|word_index| {
+            let word = self.word(word_index);
+            (word != 0).then(|| word_index * 64 + 63 - word.leading_zeros() as usize)
+        })
+    }
+
+    /// Number of zero bits before the first set bit, counting from index zero like [u64::trailing_zeros].
+    ///
+    /// Returns `num_bits` for an all-zero bitmap.
+    pub fn trailing_zeros(&self) -> usize {
+        self.find_first_set().unwrap_or(self.num_bits)
+    }
+
+    /// Number of zero bits after the last set bit, counting from the end like [u64::leading_zeros].
+    ///
+    /// Returns `num_bits` for an all-zero bitmap.
+    pub fn leading_zeros(&self) -> usize {
+        self.find_last_set()
+            .map_or(self.num_bits, |last| self.num_bits - last - 1)
+    }
+
+    /// Number of set bits before the first unset bit, counting from index zero like [u64::trailing_ones].
+    ///
+    /// Returns `num_bits` for an all-ones bitmap.
+    pub fn trailing_ones(&self) -> usize {
+        self.next_with_value(0, false)
+    }
 
-        for i in 0..bools.len() {
-            assert_eq!(bools[i], bitmap.get(i).unwrap());
+    /// Number of set bits after the last unset bit, counting from the end like [u64::leading_ones].
+    ///
+    /// Returns `num_bits` for an all-ones bitmap.
+    pub fn leading_ones(&self) -> usize {
+        let num_words = self.num_words();
+        for i in (0..num_words).rev() {
+            let valid = if i + 1 == num_words {
+                self.last_word_mask()
+            } else {
+                u64::MAX
+            };
+            let zeros = !self.word(i) & valid;
+            if zeros != 0 {
+                let last_zero = i * 64 + 63 - zeros.leading_zeros() as usize;
+                return self.num_bits - last_zero - 1;
+            }
         }
+        self.num_bits
+    }
 
-        let offset = bitmap.num_bits() / 2;
-        let new_len = bitmap.num_bits().saturating_sub(offset);
-        let shifted_bitmap = bitmap.slice(offset, new_len);
+    /// Returns true if exactly one bit is set, stops as soon as a second set bit is found.
+    pub fn has_single_set_bit(&self) -> bool {
+        let mut found = false;
+        for word in self.words() {
+            if word == 0 {
+                continue;
+            }
+            if found || word != word & word.wrapping_neg() {
+                return false;
+            }
+            found = true;
+        }
+        found
+    }
 
-        for i in 0..new_len {
-            assert_eq!(
-                bitmap.get(i + offset).unwrap(),
-                shifted_bitmap.get(i).unwrap(),
-                "failed at idx {}, len is: {}",
-                i,
-                bools.len()
-            );
+    /// Returns a bitmap of the same length with only the lowest set bit of `self` kept.
+    ///
+    /// Returns an all-zero bitmap if no bits are set.
+    pub fn keep_lowest_set(&self) -> Bitmap {
+        let mut found = false;
+        Bitmap::from_word_fn(self.num_bits, |i| {
+            if found {
+                return 0;
+            }
+            let word = self.word(i);
+            found = word != 0;
+            word & word.wrapping_neg()
+        })
+    }
+
+    /// Returns a bitmap of the same length with only the highest set bit of `self` kept.
+    ///
+    /// Returns an all-zero bitmap if no bits are set.
+    pub fn keep_highest_set(&self) -> Bitmap {
+        let mut bitmap = Bitmap::new(self.num_bits);
+        if let Some(last) = self.find_last_set() {
+            bitmap.set(last);
         }
+        bitmap
     }
 
-    fn generate(len: usize) -> Vec<bool> {
-        let mut buf = Vec::with_capacity(len);
+    /// Iterates over the gaps between consecutive set bits as `(prev_set, next_set, gap_len)`,
+    ///  where `gap_len` is the number of unset bits between the two indices.
+    ///
+    /// The gap before the first set bit and the gap after the last set bit aren't included, see
+    ///  [Self::trailing_zeros] and [Self::leading_zeros] for those.
+    pub fn iter_gaps(&self) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let mut set_bits = self.iter_set_bits();
+        let mut prev = set_bits.next();
 
-        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        std::iter::from_fn(move || {
+            let prev_set = prev?;
+            let next_set = set_bits.next()?;
+            prev = Some(next_set);
+            Some((prev_set, next_set, next_set - prev_set - 1))
+        })
+    }
 
-        for _ in 0..len {
-            buf.push(rng.gen_bool(0.5));
+    /// Iterates over the delta encoding of the set bit indices, the index of the first set bit
+    ///  followed by the distance from each set bit to the next one.
+    pub fn set_bit_deltas(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut prev = 0;
+        self.iter_set_bits().map(move |index| {
+            let delta = index - prev;
+            prev = index;
+            delta
+        })
+    }
+
+    /// Returns the bitmap without the zero bits after the last set bit.
+    ///
+    /// An all-zero bitmap trims to an empty one. Doesn't copy the bitmap.
+    pub fn trim_trailing_zeros(&self) -> Bitmap {
+        match self.find_last_set() {
+            Some(last) => self.slice(0, last + 1),
+            None => Bitmap::new(0),
         }
+    }
 
-        buf
+    /// Returns the number of zero bits before the first set bit and the bitmap without them.
+    ///
+    /// An all-zero bitmap trims to an empty one, with all of its bits counted as dropped.
+    pub fn trim_leading_zeros(&self) -> (usize, Bitmap) {
+        match self.find_first_set() {
+            Some(first) => (first, self.slice(first, self.num_bits - first)),
+            None => (self.num_bits, Bitmap::new(0)),
+        }
+    }
+
+    /// Returns the index of the first set bit and the bitmap covering only the bits from the
+    ///  first set bit to the last set bit, both inclusive.
+    ///
+    /// An all-zero bitmap returns `(0, Bitmap::new(0))`.
+    pub fn trim(&self) -> (usize, Bitmap) {
+        match (self.find_first_set(), self.find_last_set()) {
+            (Some(first), Some(last)) => (first, self.slice(first, last - first + 1)),
+            _ => (0, Bitmap::new(0)),
+        }
+    }
+
+    /// Returns the indices of the first `n` set bits in ascending order.
+    ///
+    /// Stops scanning as soon as `n` set bits are found.
+    pub fn first_n_set(&self, n: usize) -> Vec<usize> {
+        self.iter_set_bits().take(n).collect()
+    }
+
+    /// Returns the indices of the last `n` set bits in ascending order.
+    ///
+    /// Scans from the end and stops as soon as `n` set bits are found.
+    pub fn last_n_set(&self, n: usize) -> Vec<usize> {
+        let mut indices = Vec::with_capacity(n.min(self.num_bits));
+
+        'words: for word_index in (0..self.num_words()).rev() {
+            let mut word = self.word(word_index);
+            while word != 0 {
+                if indices.len() == n {
+                    break 'words;
+                }
+                let bit = 63 - word.leading_zeros() as usize;
+                indices.push(word_index * 64 + bit);
+                word &= !(1 << bit);
+            }
+        }
+
+        indices.reverse();
+        indices
+    }
+
+    /// Returns the set bit ranges in the bitmap as `(start, len)` pairs
+    pub fn set_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges = Vec::new();
+        self.set_ranges_into(&mut ranges);
+        ranges
+    }
+
+    /// Same as [Self::set_ranges] but clears `out` and writes the ranges into it, so the
+    ///  allocation can be reused across calls.
+    pub fn set_ranges_into(&self, out: &mut Vec<(usize, usize)>) {
+        out.clear();
+        crate::compute::push_set_ranges(self.words(), self.num_bits, out);
+    }
+
+    /// Returns the longest run of set bits as `(start, len)`, the first one if there are ties.
+    ///
+    /// Returns `(0, 0)` if no bits are set.
+    pub fn longest_set_run(&self) -> (usize, usize) {
+        self.longest_run(true)
+    }
+
+    /// Returns the longest run of unset bits as `(start, len)`, the first one if there are ties.
+    ///
+    /// Returns `(0, 0)` if all bits are set.
+    pub fn longest_zero_run(&self) -> (usize, usize) {
+        self.longest_run(false)
+    }
+
+    /// Longest run of bits equal to `value` as `(start, len)`, `(0, 0)` if there is none.
+    fn longest_run(&self, value: bool) -> (usize, usize) {
+        self.iter_runs()
+            .filter(|&(run_value, _, _)| run_value == value)
+            .fold(
+                (0, 0),
+                |best, (_, start, len)| {
+                    if len > best.1 {
+                        (start, len)
+                    } else {
+                        best
+                    }
+                },
+            )
+    }
+
+    /// Returns the `n`-th set bit range as `(start, len)`, same as `self.set_ranges().get(n)`.
+    ///
+    /// Runs are scanned lazily and the scan stops at the `n`-th one.
+    pub fn nth_set_run(&self, n: usize) -> Option<(usize, usize)> {
+        let mut pos = 0;
+        let mut remaining = n;
+        loop {
+            let start = self.next_with_value(pos, true);
+            if start == self.num_bits {
+                return None;
+            }
+            let end = self.next_with_value(start, false);
+            if remaining == 0 {
+                return Some((start, end - start));
+            }
+            remaining -= 1;
+            pos = end;
+        }
+    }
+
+    /// Returns the first run of set bits with at least `min_len` bits as `(start, len)`.
+    ///
+    /// Runs are scanned lazily and the scan stops at the first long enough one.
+    pub fn first_run_at_least(&self, min_len: usize) -> Option<(usize, usize)> {
+        self.first_run_of_at_least(true, min_len)
+    }
+
+    /// Returns the start of the first `len` consecutive unset bits, treating the bitmap as an
+    ///  allocation map where set bits are used.
+    ///
+    /// Bits past `num_bits` are never counted as free. A `len` of zero is found at index zero.
+    pub fn find_free_range(&self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return Some(0);
+        }
+
+        self.first_run_of_at_least(false, len)
+            .map(|(start, _)| start)
+    }
+
+    /// Finds the first `len` free bits with [Self::find_free_range], marks them as used by setting
+    ///  them and returns their start. `None` if there is no free range big enough.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    pub fn alloc(&mut self, len: usize) -> Option<usize> {
+        let start = self.find_free_range(len)?;
+        self.set_range(start, len, true);
+        Some(start)
+    }
+
+    /// Releases a range returned by [Self::alloc] by unsetting `[start, start + len)`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    pub fn free(&mut self, start: usize, len: usize) {
+        self.set_range(start, len, false);
+    }
+
+    /// First run of bits equal to `value` with at least `min_len` bits as `(start, len)`.
+    fn first_run_of_at_least(&self, value: bool, min_len: usize) -> Option<(usize, usize)> {
+        self.iter_runs()
+            .find(|&(run_value, _, len)| run_value == value && len >= min_len)
+            .map(|(_, start, len)| (start, len))
+    }
+
+    /// Same as [Self::set_ranges] but ranges separated by less than `min_gap` unset bits are
+    ///  merged into a single `(start, len)` range.
+    ///
+    /// Merged ranges include the unset bits between them. A `min_gap` of zero or one doesn't
+    ///  merge anything since ranges are always separated by at least one unset bit.
+    pub fn merge_ranges(&self, min_gap: usize) -> Vec<(usize, usize)> {
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+
+        for (start, len) in self.set_ranges() {
+            match merged.last_mut() {
+                Some((prev_start, prev_len)) if start - (*prev_start + *prev_len) < min_gap => {
+                    *prev_len = start + len - *prev_start;
+                }
+                _ => merged.push((start, len)),
+            }
+        }
+
+        merged
+    }
+
+    /// Returns a new bitmap of `self.num_bits()` bits holding `self | (other shifted by shift)`.
+    ///
+    /// A positive `shift` moves bit `i` of `other` to `i + shift`, a negative one moves it to
+    ///  `i - |shift|`. Bits shifted outside of `self` are dropped, `other` can have any length.
+    pub fn or_shifted(&self, other: &Bitmap, shift: isize) -> Bitmap {
+        let mut out = Bitmap::from_word_fn(self.num_bits, |i| self.word(i));
+
+        let distance = shift.unsigned_abs();
+        let (dst_start, src_start) = if shift >= 0 {
+            (distance, 0)
+        } else {
+            (0, distance)
+        };
+
+        if dst_start >= self.num_bits || src_start >= other.num_bits {
+            return out;
+        }
+
+        // Words of `other` from the one holding `src_start`, behind a zero word so that bit can be
+        // moved to bit `dst_start % 64` with the right shift of re_align even when that is a left
+        // shift. re_align only fills the last word from its own source word, so read one more.
+        let dst_word = dst_start / 64;
+        let num_words = out.num_words() - dst_word;
+        let src_bit = 64 + src_start % 64 - dst_start % 64;
+        let mut src = vec![0u64; src_bit / 64 + num_words + 1];
+        let other_words = (src_start / 64..other.num_words()).map(|i| other.word(i));
+        for (dst, word) in src[1..].iter_mut().zip(other_words) {
+            *dst = word.to_le();
+        }
+
+        let mut aligned = vec![0u64; num_words + 1];
+        unsafe {
+            crate::compute::re_align(
+                src.as_ptr().add(src_bit / 64),
+                aligned.as_mut_ptr(),
+                num_words + 1,
+                (src_bit % 64) as u32,
+            );
+        }
+
+        for (dst, word) in out.words_mut()[dst_word..].iter_mut().zip(aligned) {
+            *dst = (u64::from_le(*dst) | u64::from_le(word)).to_le();
+        }
+        out.mask_last_word();
+
+        out
+    }
+
+    /// Returns a new bitmap where each set run is expanded by `k` bits on both sides, clamped to
+    ///  the bitmap. Runs that overlap after expanding are merged.
+    pub fn dilate(&self, k: usize) -> Bitmap {
+        let mut out = Bitmap::new(self.num_bits);
+
+        for (start, len) in self.set_ranges() {
+            let new_start = start.saturating_sub(k);
+            let new_end = (start + len).saturating_add(k).min(self.num_bits);
+            out.set_range(new_start, new_end - new_start, true);
+        }
+
+        out
+    }
+
+    /// Returns a new bitmap where each set run is shrunk by `k` bits on both sides.
+    ///
+    /// This is the dual of [Self::dilate], so runs touching the ends of the bitmap aren't shrunk
+    ///  at that end. `erode(k)` undoes `dilate(k)` for runs that are more than `2 * k` bits apart.
+    pub fn erode(&self, k: usize) -> Bitmap {
+        !&(!self).dilate(k)
+    }
+
+    /// Returns the `(start, len)` ranges where both `self` and `other` are set.
+    ///
+    /// Same as `(self & other).set_ranges()` without allocating the intersection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn overlap_ranges(&self, other: &Bitmap) -> Vec<(usize, usize)> {
+        self.zip_set_ranges(other, |a, b| a & b)
+    }
+
+    /// Returns the `(start, len)` ranges where `self` and `other` differ.
+    ///
+    /// Same as `(self ^ other).set_ranges()` without allocating the difference.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn changed_ranges(&self, other: &Bitmap) -> Vec<(usize, usize)> {
+        self.zip_set_ranges(other, |a, b| a ^ b)
+    }
+
+    /// Iterates over the indices where `self` and `other` differ, paired with the value of the
+    ///  bit in `other`, in ascending order.
+    ///
+    /// Only the first `min(self.num_bits(), other.num_bits())` bits are compared.
+    pub fn diff_iter<'a>(&'a self, other: &'a Bitmap) -> impl Iterator<Item = (usize, bool)> + 'a {
+        let num_bits = self.num_bits.min(other.num_bits);
+        let num_words = num_bits.div_ceil(64);
+        let last_mask = match num_bits % 64 {
+            0 => u64::MAX,
+            rem => (1 << rem) - 1,
+        };
+
+        (0..num_words).flat_map(move |i| {
+            let other_word = other.word(i);
+            let mut diff = self.word(i) ^ other_word;
+            if i + 1 == num_words {
+                diff &= last_mask;
+            }
+            set_bit_positions(diff, i * 64)
+                .map(move |index| (index, other_word & (1 << (index % 64)) != 0))
+        })
+    }
+
+    /// Returns a new bitmap with only the first bit of each set run of `self` set.
+    pub fn run_starts(&self) -> Bitmap {
+        Bitmap::from_word_fn(self.num_bits, |i| {
+            let word = self.word(i);
+            let carry = if i > 0 { self.word(i - 1) >> 63 } else { 0 };
+            word & !((word << 1) | carry)
+        })
+    }
+
+    pub fn from_bools(bools: &[bool]) -> Self {
+        assert_num_bits(bools.len());
+        Self::pack_bools(bools, Buffer::new(bools.len().div_ceil(8)))
+    }
+
+    /// Same as [Self::from_bools] but returns an error instead of panicking if the bitmap is too
+    ///  large.
+    ///
+    /// # Errors
+    ///
+    /// Returns [BuildError::CapacityOverflow] if `bools.len() > isize::MAX`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory can't be allocated, [Buffer] has no fallible constructor to report
+    ///  allocation failures with.
+    pub fn try_from_bools(bools: &[bool]) -> Result<Self, BuildError> {
+        let buf = try_new_buffer(bools.len())?;
+        Ok(Self::pack_bools(bools, buf))
+    }
+
+    /// Packs `bools` into a freshly allocated `buf` that holds at least `bools.len().div_ceil(8)` bytes.
+    fn pack_bools(bools: &[bool], mut buf: Buffer) -> Self {
+        pack_bools_into(bools, buf.as_mut_slice());
+
+        let bitmap = Self {
+            buf: Arc::new(buf),
+            num_bits: bools.len(),
+        };
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Replaces the contents of the bitmap with `bools`, see [Self::from_bools].
+    ///
+    /// Reuses the underlying buffer if it isn't shared and is big enough, allocates a new one otherwise.
+    pub fn fill_from_bools(&mut self, bools: &[bool]) {
+        let num_bytes = bools.len().div_ceil(8);
+
+        match Arc::get_mut(&mut self.buf) {
+            Some(buf) if buf.len() >= num_bytes => {
+                pack_bools_into(bools, buf.as_mut_slice());
+                self.num_bits = bools.len();
+                self.clear_padding_bits();
+            }
+            _ => *self = Self::from_bools(bools),
+        }
+    }
+
+    /// Create a bitmap from `(value, run_length)` runs, the bitmap is the sum of the run lengths long.
+    ///
+    /// Runs are written a word at a time and the buffer grows geometrically, so the runs don't
+    ///  have to be collected first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total number of bits overflows.
+    pub fn from_run_iter<I: IntoIterator<Item = (bool, usize)>>(iter: I) -> Self {
+        let mut bitmap = Self::new(0);
+
+        for (value, len) in iter {
+            let start = bitmap.num_bits;
+            let num_bits = start.checked_add(len).unwrap();
+
+            bitmap.reserve_bits_amortized(num_bits);
+
+            // Bits past the end are kept zeroed, so only set runs have to be written
+            bitmap.num_bits = num_bits;
+            if value {
+                bitmap.set_range(start, len, true);
+            }
+        }
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Create a bitmap from alternating run lengths written by [Self::to_run_lengths], the first
+    ///  run is of unset bits.
+    ///
+    /// Runs of zero length are allowed anywhere.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total number of bits overflows.
+    pub fn from_run_lengths(lengths: &[usize]) -> Self {
+        Self::from_run_iter(
+            lengths
+                .iter()
+                .enumerate()
+                .map(|(i, &len)| (i % 2 == 1, len)),
+        )
+    }
+
+    /// Create a bitmap of `num_bits` bits where only the bits at given indices are set.
+    ///
+    /// Indices don't have to be sorted or unique.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is out of bounds.
+    pub fn from_indices(num_bits: usize, indices: &[usize]) -> Self {
+        let mut bitmap = Self::new(num_bits);
+        for &index in indices {
+            bitmap.set(index);
+        }
+        bitmap
+    }
+
+    /// Create a bitmap from `num_bits` bits of `bytes` starting at bit `bit_offset`, with bits
+    ///  numbered least significant first within each byte.
+    ///
+    /// The bits are re-aligned to start at index zero the same way as [Self::slice].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes` doesn't hold `bit_offset + num_bits` bits.
+    pub fn from_packed_with_offset(bytes: &[u8], bit_offset: usize, num_bits: usize) -> Self {
+        let end = bit_offset.checked_add(num_bits).unwrap();
+        assert!(
+            end.div_ceil(8) <= bytes.len(),
+            "{} bits starting at bit {} are out of bounds for {} bytes",
+            num_bits,
+            bit_offset,
+            bytes.len()
+        );
+
+        let first_byte = bit_offset / 8;
+        let shift = bit_offset % 8;
+
+        let mut src = Self::new(end - first_byte * 8);
+        let num_bytes = src.num_bytes();
+        src.as_mut_byte_slice()
+            .copy_from_slice(&bytes[first_byte..first_byte + num_bytes]);
+
+        let mut bitmap = if shift == 0 {
+            src
+        } else {
+            src.slice(shift, num_bits)
+        };
+        bitmap.clear_padding_bits();
+
+        bitmap
+    }
+
+    /// Create a bitmap of `num_bits` bits where only the bits in given `(start, len)` ranges are set.
+    ///
+    /// Ranges don't have to be sorted and can overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is outside of the bitmap.
+    pub fn from_set_ranges(num_bits: usize, ranges: &[(usize, usize)]) -> Self {
+        let mut bitmap = Self::new(num_bits);
+        for &(start, len) in ranges {
+            bitmap.set_range(start, len, true);
+        }
+        bitmap
+    }
+
+    /// Create a bitmap where the bit at index `i` is `f(i)`.
+    ///
+    /// `f` is called in ascending index order and the results are packed a word at a time.
+    pub fn from_fn<F: FnMut(usize) -> bool>(num_bits: usize, mut f: F) -> Self {
+        let mut bitmap = Self::new(num_bits);
+
+        for (word_index, dst) in bitmap.words_mut().iter_mut().enumerate() {
+            let base = word_index * 64;
+            let mut word = 0u64;
+            for shift in 0..(num_bits - base).min(64) {
+                word |= u64::from(f(base + shift)) << shift;
+            }
+            *dst = word.to_le();
+        }
+        #[cfg(debug_assertions)]
+        bitmap.assert_invariants();
+
+        bitmap
+    }
+
+    /// Create a random bitmap where each bit is set with probability `density`.
+    ///
+    /// The output is deterministic for a given `seed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `density` is not in `[0, 1]`.
+    #[cfg(feature = "rand")]
+    pub fn sample(num_bits: usize, density: f64, seed: u64) -> Self {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(seed);
+        Self::from_fn(num_bits, |_| rng.gen_bool(density))
+    }
+
+    #[inline(always)]
+    /// Returns a shared pointer to the underlying buffer
+    pub fn buf(&self) -> Arc<Buffer> {
+        self.buf.clone()
+    }
+
+    #[inline(always)]
+    pub fn get(&self, bit_index: usize) -> Option<bool> {
+        if bit_index >= self.num_bits {
+            return None;
+        }
+
+        Some(unsafe { self.get_unchecked(bit_index) })
+    }
+
+    /// Same as [Self::get] but returns an error for out of range indices.
+    #[inline(always)]
+    pub fn checked_get(&self, bit_index: usize) -> Result<bool, OutOfBounds> {
+        self.get(bit_index).ok_or(OutOfBounds {
+            index: bit_index,
+            num_bits: self.num_bits,
+        })
+    }
+
+    /// Returns if the bit at given index is set. Out of range indices are treated as unset.
+    #[inline(always)]
+    pub fn is_set(&self, bit_index: usize) -> bool {
+        self.get(bit_index).unwrap_or(false)
+    }
+
+    /// # Safety
+    ///
+    /// `bit_index` should be less than `self.num_bits()`
+    #[inline(always)]
+    pub unsafe fn get_unchecked(&self, bit_index: usize) -> bool {
+        let byte_index = bit_index / 8;
+        let mask = 1 << (bit_index % 8);
+
+        // Catches bitmaps that were created with a buffer that is too small
+        debug_assert!(
+            byte_index < self.buf.len(),
+            "byte {} is out of bounds for buffer of {} bytes",
+            byte_index,
+            self.buf.len()
+        );
+
+        unsafe { (*self.buf.as_ptr().add(byte_index) & mask) != 0 }
+    }
+
+    /// Sets the bit at given index to one.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit_index >= self.num_bits()`.
+    pub fn set(&mut self, bit_index: usize) {
+        assert!(bit_index < self.num_bits);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        let buf = Arc::make_mut(&mut self.buf);
+        unsafe { *buf.as_mut_ptr().add(bit_index / 8) |= 1 << (bit_index % 8) };
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Sets the bit at given index to zero.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit_index >= self.num_bits()`.
+    pub fn clear(&mut self, bit_index: usize) {
+        assert!(bit_index < self.num_bits);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        let buf = Arc::make_mut(&mut self.buf);
+        unsafe { *buf.as_mut_ptr().add(bit_index / 8) &= !(1 << (bit_index % 8)) };
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Applies a batch of `(index, value)` edits, setting the bit at `index` to `value`.
+    ///
+    /// Edits are grouped by word so each word is read and written once. If an index is edited
+    ///  more than once the last edit wins. Edits with an index past `num_bits` are skipped.
+    ///  Copies the underlying buffer if it is shared with another bitmap.
+    pub fn apply_edits(&mut self, edits: &[(usize, bool)]) {
+        let mut edits = edits
+            .iter()
+            .copied()
+            .filter(|&(index, _)| index < self.num_bits)
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return;
+        }
+        // Stable sort keeps the order of edits to the same index
+        edits.sort_by_key(|&(index, _)| index);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        let words = self.words_mut();
+        for group in edits.chunk_by(|a, b| a.0 / 64 == b.0 / 64) {
+            let word_index = group[0].0 / 64;
+            let mut word = u64::from_le(words[word_index]);
+            for &(index, value) in group {
+                let bit = 1 << (index % 64);
+                if value {
+                    word |= bit;
+                } else {
+                    word &= !bit;
+                }
+            }
+            words[word_index] = word.to_le();
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Reads `len` bits starting at `start` into the low bits of a `u64`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len > 64` or if given range is outside of the bitmap.
+    pub fn get_bits_u64(&self, start: usize, len: usize) -> u64 {
+        assert!(len <= 64);
+        assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+        if len == 0 {
+            return 0;
+        }
+
+        let word_index = start / 64;
+        let shift = start % 64;
+
+        let mut bits = self.word(word_index) >> shift;
+        if shift + len > 64 {
+            bits |= self.word(word_index + 1) << (64 - shift);
+        }
+
+        bits & low_bits_mask(len)
+    }
+
+    /// Writes the low `len` bits of `value` into the bitmap starting at `start`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len > 64` or if given range is outside of the bitmap.
+    pub fn set_bits_u64(&mut self, start: usize, len: usize, value: u64) {
+        assert!(len <= 64);
+        assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+        if len == 0 {
+            return;
+        }
+
+        let word_index = start / 64;
+        let shift = start % 64;
+        let mask = low_bits_mask(len);
+        let value = value & mask;
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        let words = self.words_mut();
+
+        let lo = u64::from_le(words[word_index]);
+        words[word_index] = ((lo & !(mask << shift)) | (value << shift)).to_le();
+
+        if shift + len > 64 {
+            let hi_mask = low_bits_mask(shift + len - 64);
+            let hi = u64::from_le(words[word_index + 1]);
+            words[word_index + 1] = ((hi & !hi_mask) | (value >> (64 - shift))).to_le();
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Sets all bits in `[start, start + len)` to `value`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    pub fn set_range(&mut self, start: usize, len: usize, value: bool) {
+        assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+        if len == 0 {
+            return;
+        }
+
+        let fill = if value { u64::MAX } else { 0 };
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+        let words = self.words_mut();
+        for (i, mask) in range_word_masks(start, len) {
+            words[i] = ((u64::from_le(words[i]) & !mask) | (fill & mask)).to_le();
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Sets all bits in each of the given `(start, len)` ranges.
+    ///
+    /// Ranges don't have to be sorted and can overlap, they are coalesced before being filled.
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is outside of the bitmap.
+    pub fn set_ranges_bits(&mut self, ranges: &[(usize, usize)]) {
+        let mut ranges = ranges
+            .iter()
+            .map(|&(start, len)| {
+                let end = start.checked_add(len).unwrap();
+                assert!(end <= self.num_bits);
+                (start, end)
+            })
+            .filter(|&(start, end)| start < end)
+            .collect::<Vec<_>>();
+        if ranges.is_empty() {
+            return;
+        }
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+        let words = self.words_mut();
+        for (start, end) in merged {
+            for (i, mask) in range_word_masks(start, end - start) {
+                words[i] = (u64::from_le(words[i]) | mask).to_le();
+            }
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Changes the number of bits to `num_bits`, new bits are set to `fill`.
+    ///
+    /// Reallocates if the buffer can't hold `num_bits`. Bits dropped when shrinking are zeroed.
+    pub fn resize(&mut self, num_bits: usize, fill: bool) {
+        if num_bits <= self.num_bits {
+            self.num_bits = num_bits;
+            self.clear_padding_bits();
+            return;
+        }
+
+        self.reserve_bytes(num_bits.div_ceil(8));
+
+        let old_num_bits = self.num_bits;
+        self.num_bits = num_bits;
+        self.set_range(old_num_bits, num_bits - old_num_bits, fill);
+        self.clear_padding_bits();
+    }
+
+    /// Appends a bit to the end of the bitmap, growing the buffer geometrically if it is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits overflows.
+    pub fn append_bit(&mut self, value: bool) {
+        let bit_index = self.num_bits;
+        self.reserve_bits_amortized(bit_index.checked_add(1).unwrap());
+        self.num_bits += 1;
+
+        // The bit might be dirty padding of a shared buffer so it is always written
+        if value {
+            self.set(bit_index);
+        } else {
+            self.clear(bit_index);
+        }
+    }
+
+    /// Removes the last bit of the bitmap and returns it, `None` if the bitmap is empty.
+    ///
+    /// The removed bit is zeroed so the padding stays clean. Copies the underlying buffer if it is
+    ///  shared with another bitmap.
+    pub fn pop_bit(&mut self) -> Option<bool> {
+        let bit_index = self.num_bits.checked_sub(1)?;
+        let value = self.is_set(bit_index);
+        self.clear(bit_index);
+        self.num_bits = bit_index;
+        Some(value)
+    }
+
+    /// Sets the number of bits to exactly `num_bits`, zero-extending when growing.
+    ///
+    /// All bits of the underlying buffer at or above `num_bits` are zeroed afterwards, even if
+    ///  they were set before, e.g. after [Self::as_mut_word_slice].
+    pub fn clamp_to_length(&mut self, num_bits: usize) {
+        self.resize(num_bits, false);
+    }
+
+    /// Increases the number of bits by `additional_bits`, the new bits are set to `fill`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits overflows.
+    pub fn grow_by(&mut self, additional_bits: usize, fill: bool) {
+        self.resize(self.num_bits.checked_add(additional_bits).unwrap(), fill);
+    }
+
+    /// Copies `len` bits from `src` starting at `src_start` into `self` starting at `dst_start`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range is outside of its bitmap.
+    pub fn copy_bits_from(&mut self, dst_start: usize, src: &Bitmap, src_start: usize, len: usize) {
+        assert!(dst_start.checked_add(len).unwrap() <= self.num_bits);
+        assert!(src_start.checked_add(len).unwrap() <= src.num_bits);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        for offset in (0..len).step_by(64) {
+            let chunk_len = (len - offset).min(64);
+            let bits = src.get_bits_u64(src_start + offset, chunk_len);
+            self.set_bits_u64(dst_start + offset, chunk_len, bits);
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Overwrites `[start, start + other.num_bits())` with the bits of `other`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the replaced range is outside of the bitmap.
+    pub fn replace_range(&mut self, start: usize, other: &Bitmap) {
+        self.copy_bits_from(start, other, 0, other.num_bits);
+    }
+
+    /// Returns a new bitmap holding the bits of `self` followed by the bits of `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total number of bits overflows.
+    pub fn concat(&self, other: &Bitmap) -> Bitmap {
+        self.checked_concat(other).expect("bitmap too large")
+    }
+
+    /// Same as [Self::concat] but returns `None` instead of panicking if the total number of bits
+    /// overflows or is over `isize::MAX`.
+    ///
+    /// Goes through the same allocation as [Self::try_from_bools], so it still panics if the
+    /// memory can't be allocated.
+    pub fn checked_concat(&self, other: &Bitmap) -> Option<Bitmap> {
+        let num_bits = self.num_bits.checked_add(other.num_bits)?;
+        let mut out = Bitmap::try_new(num_bits).ok()?;
+        out.copy_bits_from(0, self, 0, self.num_bits);
+        out.copy_bits_from(self.num_bits, other, 0, other.num_bits);
+
+        Some(out)
+    }
+
+    /// Returns a new bitmap holding the bits of all given bitmaps in order.
+    ///
+    /// Allocates the result once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total number of bits overflows.
+    pub fn concat_many(parts: &[Bitmap]) -> Bitmap {
+        let num_bits = parts
+            .iter()
+            .try_fold(0usize, |acc, part| acc.checked_add(part.num_bits))
+            .unwrap();
+
+        let mut out = Bitmap::new(num_bits);
+        let mut offset = 0;
+        for part in parts {
+            out.copy_bits_from(offset, part, 0, part.num_bits);
+            offset += part.num_bits;
+        }
+
+        out
+    }
+
+    /// Returns a new bitmap where the bit at index `i` is moved to `i + n`, bits moved past the
+    ///  end are dropped and the low `n` bits are zero.
+    pub fn shift_left(&self, n: usize) -> Bitmap {
+        Bitmap::from_word_fn(self.num_bits, |i| self.shifted_left_word(i, n))
+    }
+
+    /// Returns a new bitmap where the bit at index `i` is moved to `i - n`, bits moved below zero
+    ///  are dropped and the high `n` bits are zero.
+    pub fn shift_right(&self, n: usize) -> Bitmap {
+        Bitmap::from_word_fn(self.num_bits, |i| self.shifted_right_word(i, n))
+    }
+
+    /// In place version of [Self::shift_left].
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    pub fn shift_left_in_place(&mut self, n: usize) {
+        self.mask_last_word();
+
+        let (word_shift, bit_shift) = (n / 64, n % 64);
+        let words = self.words_mut();
+        for i in (0..words.len()).rev() {
+            let word = match i.checked_sub(word_shift) {
+                Some(src) => {
+                    let mut word = u64::from_le(words[src]) << bit_shift;
+                    if bit_shift != 0 && src > 0 {
+                        word |= u64::from_le(words[src - 1]) >> (64 - bit_shift);
+                    }
+                    word
+                }
+                None => 0,
+            };
+            words[i] = word.to_le();
+        }
+
+        self.clear_padding_bits();
+    }
+
+    /// In place version of [Self::shift_right].
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    pub fn shift_right_in_place(&mut self, n: usize) {
+        self.mask_last_word();
+
+        let (word_shift, bit_shift) = (n / 64, n % 64);
+        let words = self.words_mut();
+        let num_words = words.len();
+        for i in 0..num_words {
+            let word = match i.checked_add(word_shift) {
+                Some(src) if src < num_words => {
+                    let mut word = u64::from_le(words[src]) >> bit_shift;
+                    if bit_shift != 0 && src + 1 < num_words {
+                        word |= u64::from_le(words[src + 1]) << (64 - bit_shift);
+                    }
+                    word
+                }
+                _ => 0,
+            };
+            words[i] = word.to_le();
+        }
+
+        self.clear_padding_bits();
+    }
+
+    /// Advances the bitmap as a sliding window by `k` bits, same as [Self::shift_right_in_place].
+    ///
+    /// Bits `[0, k)` are dropped, the remaining bits move down by `k` and the top `k` bits are
+    ///  zero. The number of bits stays the same.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    pub fn advance(&mut self, k: usize) {
+        self.shift_right_in_place(k);
+    }
+
+    /// Same as `&self.shift_right(n) & mask` without allocating the shifted bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != mask.num_bits()`.
+    pub fn shift_right_and(&self, n: usize, mask: &Bitmap) -> Bitmap {
+        assert_eq!(self.num_bits, mask.num_bits);
+
+        Bitmap::from_word_fn(self.num_bits, |i| {
+            self.shifted_right_word(i, n) & mask.word(i)
+        })
+    }
+
+    /// Returns a new bitmap where the bit at index `i` is moved to `(i + n) % num_bits`.
+    pub fn rotate_left(&self, n: usize) -> Bitmap {
+        if self.num_bits == 0 {
+            return Bitmap::new(0);
+        }
+
+        let n = n % self.num_bits;
+        let mut out = Bitmap::new(self.num_bits);
+        out.copy_bits_from(n, self, 0, self.num_bits - n);
+        out.copy_bits_from(0, self, self.num_bits - n, n);
+
+        out
+    }
+
+    /// Returns a new bitmap where the bit at index `i` is moved to `(i - n) mod num_bits`.
+    pub fn rotate_right(&self, n: usize) -> Bitmap {
+        if self.num_bits == 0 {
+            return Bitmap::new(0);
+        }
+
+        self.rotate_left(self.num_bits - n % self.num_bits)
+    }
+
+    /// Same as `self.rotate_left(8 * byte_count)`, but rotates whole bytes instead of shifting bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits()` isn't a multiple of 8.
+    pub fn rotate_bytes_left(&self, byte_count: usize) -> Bitmap {
+        assert!(
+            self.num_bits.is_multiple_of(8),
+            "rotate_bytes_left requires a whole number of bytes, got {} bits",
+            self.num_bits
+        );
+
+        let mut out = Bitmap::new(self.num_bits);
+        let num_bytes = self.num_bits / 8;
+        if num_bytes == 0 {
+            return out;
+        }
+
+        let dst = out.as_mut_byte_slice();
+        dst.copy_from_slice(self.as_byte_slice());
+        dst.rotate_right(byte_count % num_bytes);
+
+        out
+    }
+
+    /// Swaps the values of the bits at given indices.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either index is outside of the bitmap.
+    pub fn swap_bits(&mut self, a: usize, b: usize) {
+        let a_value = self.get(a).unwrap();
+        let b_value = self.get(b).unwrap();
+
+        if a_value != b_value {
+            #[cfg(debug_assertions)]
+            let padding = self.last_word_padding();
+            self.set_bits_u64(a, 1, u64::from(b_value));
+            self.set_bits_u64(b, 1, u64::from(a_value));
+            #[cfg(debug_assertions)]
+            self.assert_padding_kept(padding);
+        }
+    }
+
+    /// Swaps the bits in `[a_start, a_start + len)` with the bits in `[b_start, b_start + len)`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ranges overlap or if either range is outside of the bitmap.
+    pub fn swap_ranges(&mut self, a_start: usize, b_start: usize, len: usize) {
+        let a_end = a_start.checked_add(len).unwrap();
+        let b_end = b_start.checked_add(len).unwrap();
+        assert!(a_end <= self.num_bits && b_end <= self.num_bits);
+        assert!(a_end <= b_start || b_end <= a_start);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        for offset in (0..len).step_by(64) {
+            let chunk_len = (len - offset).min(64);
+            let a_bits = self.get_bits_u64(a_start + offset, chunk_len);
+            let b_bits = self.get_bits_u64(b_start + offset, chunk_len);
+            self.set_bits_u64(a_start + offset, chunk_len, b_bits);
+            self.set_bits_u64(b_start + offset, chunk_len, a_bits);
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Returns the words holding the bits of the bitmap, `self.num_bits().div_ceil(64)` of them.
+    ///
+    /// Words are stored in little endian byte order, use [u64::from_le] to read them.
+    ///  Bits past `num_bits` in the last word aren't guaranteed to be zero, see [Self::clear_padding_bits].
+    pub fn as_word_slice(&self) -> &[u64] {
+        let num_words = self.num_words();
+        if num_words == 0 {
+            return &[];
+        }
+
+        unsafe { std::slice::from_raw_parts(self.buf.as_ptr() as *const u64, num_words) }
+    }
+
+    /// Mutable version of [Self::as_word_slice].
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap. Bits past `num_bits`
+    ///  must be left zeroed, or be cleared with [Self::clear_padding_bits] after writing.
+    pub fn as_mut_word_slice(&mut self) -> &mut [u64] {
+        self.words_mut()
+    }
+
+    /// Returns the bytes holding the bits of the bitmap, `self.num_bits().div_ceil(8)` of them.
+    ///
+    /// Bits past `num_bits` in the last byte aren't guaranteed to be zero, see [Self::clear_padding_bits].
+    pub fn as_byte_slice(&self) -> &[u8] {
+        &self.buf.as_slice()[..self.num_bits.div_ceil(8)]
+    }
+
+    /// Mutable version of [Self::as_byte_slice].
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap. Bits past `num_bits`
+    ///  must be left zeroed, or be cleared with [Self::clear_padding_bits] after writing.
+    pub fn as_mut_byte_slice(&mut self) -> &mut [u8] {
+        let num_bytes = self.num_bits.div_ceil(8);
+        &mut Arc::make_mut(&mut self.buf).as_mut_slice()[..num_bytes]
+    }
+
+    /// Returns the bytes of the bitmap as a `Vec` of [Self::num_bytes] bytes, bits past `num_bits`
+    ///  in the last byte are zeroed.
+    ///
+    /// Always copies. The buffer is allocated with [arrs_buffer::ALIGNMENT] alignment, so its
+    ///  allocation can't be handed to a `Vec<u8>`, which would free it with an alignment of 1.
+    pub fn into_vec_u8(self) -> Vec<u8> {
+        let mut bytes = self.as_byte_slice().to_vec();
+        if let Some(last) = bytes.last_mut() {
+            *last = self.byte_at(self.num_bytes() - 1);
+        }
+        bytes
+    }
+
+    /// Returns the byte at given index, bits past `num_bits` are zeroed and bytes past the end
+    ///  of the bitmap read as zero.
+    pub fn byte_at(&self, byte_index: usize) -> u8 {
+        let num_bytes = self.num_bits.div_ceil(8);
+        if byte_index >= num_bytes {
+            return 0;
+        }
+
+        let byte = self.buf.as_slice()[byte_index];
+        if byte_index + 1 == num_bytes && !self.num_bits.is_multiple_of(8) {
+            byte & ((1 << (self.num_bits % 8)) - 1)
+        } else {
+            byte
+        }
+    }
+
+    /// Returns the word at given index, bits past `num_bits` are zeroed and words past the end
+    ///  of the bitmap read as zero.
+    pub fn word_at(&self, word_index: usize) -> u64 {
+        if word_index < self.num_words() {
+            self.word(word_index)
+        } else {
+            0
+        }
+    }
+
+    /// Iterates over the words of the bitmap, bits past `num_bits` in the last word are zeroed.
+    pub fn iter_words(&self) -> impl Iterator<Item = u64> + '_ {
+        self.words()
+    }
+
+    /// Iterates over the words that have at least one set bit as `(word_index, word)`, bits past
+    ///  `num_bits` in the last word are zeroed.
+    pub fn iter_nonzero_words(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.words().enumerate().filter(|&(_, word)| word != 0)
+    }
+
+    /// Iterates over the words of the bitmap paired with the number of valid bits in each word.
+    ///
+    /// All words have 64 valid bits except the last one, which has `num_bits % 64` if that is
+    ///  non-zero. Bits past `num_bits` in the last word are zeroed.
+    pub fn bit_chunks(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        let num_words = self.num_words();
+        (0..num_words).map(move |i| {
+            let valid_bits = if i + 1 == num_words {
+                (self.num_bits - i * 64) as u32
+            } else {
+                64
+            };
+            (self.word(i), valid_bits)
+        })
+    }
+
+    /// Returns a bitmap where the bytes of each word are in reversed order, see [u64::swap_bytes].
+    ///
+    /// This is useful when interfacing with formats that store the words as big endian. The
+    ///  result is `self.num_bits().next_multiple_of(64)` bits long so no bits of the last word are
+    ///  lost, slice it to get the original length back after swapping twice.
+    pub fn swap_word_endianness(&self) -> Bitmap {
+        Bitmap::from_word_fn(self.num_words() * 64, |i| self.word(i).swap_bytes())
+    }
+
+    /// Replaces each word `w` of the bitmap with `f(w)`, see [Self::iter_words].
+    ///
+    /// Bits past `num_bits` in the last word are zeroed afterwards. Copies the underlying buffer
+    ///  if it is shared with another bitmap.
+    pub fn map_words<F: FnMut(u64) -> u64>(&mut self, mut f: F) {
+        let mask = self.last_word_mask();
+        let words = self.words_mut();
+        let num_words = words.len();
+
+        for (i, word) in words.iter_mut().enumerate() {
+            let mut value = u64::from_le(*word);
+            if i + 1 == num_words {
+                value &= mask;
+            }
+            *word = f(value).to_le();
+        }
+
+        self.clear_padding_bits();
+    }
+
+    /// Zeroes all bits of the underlying buffer that are past `num_bits`.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    pub fn clear_padding_bits(&mut self) {
+        self.mask_last_word();
+
+        let start = self.num_words() * 8;
+        if start < self.buf.len() {
+            Arc::make_mut(&mut self.buf).as_mut_slice()[start..].fill(0);
+        }
+        #[cfg(debug_assertions)]
+        self.assert_invariants();
+    }
+
+    /// Returns a bitmap with the same bits and clean padding, without mutating `self`.
+    ///
+    /// Shares the underlying buffer if its padding is already zero, only copies it otherwise.
+    pub fn with_padding_cleared(&self) -> Bitmap {
+        let mut bitmap = self.clone();
+        if self.validate().is_err() {
+            bitmap.clear_padding_bits();
+        }
+        bitmap
+    }
+
+    /// Number of set bits in the bitmap
+    pub fn count_ones(&self) -> usize {
+        // One cache line per block
+        self.count_ones_with_block(8)
+    }
+
+    /// Number of unset bits in the bitmap
+    pub fn count_zeros(&self) -> usize {
+        self.num_bits - self.count_ones()
+    }
+
+    /// Shannon entropy of the bits in `[0, 1]`, computed from the fraction of set bits.
+    ///
+    /// Only looks at the density, not at the order of the bits, so a bitmap of long runs with half
+    ///  of its bits set still reports 1.0. Returns 0.0 for empty, all unset and all set bitmaps.
+    pub fn entropy_bits_per_bit(&self) -> f64 {
+        if self.num_bits == 0 {
+            return 0.0;
+        }
+
+        let p = self.count_ones() as f64 / self.num_bits as f64;
+        if p == 0.0 || p == 1.0 {
+            return 0.0;
+        }
+
+        -(p * p.log2() + (1.0 - p) * (1.0 - p).log2())
+    }
+
+    /// Number of set bits in the bitmap, summed `block_words` words at a time.
+    ///
+    /// Only exists so different block sizes can be benchmarked, prefer [Self::count_ones].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_words` is zero.
+    pub fn count_ones_with_block(&self, block_words: usize) -> usize {
+        assert!(block_words > 0, "block_words must be non-zero");
+
+        let num_words = self.num_words();
+        if num_words == 0 {
+            return 0;
+        }
+
+        // The last word is read separately so the padding bits are masked out
+        let full_words = &self.as_word_slice()[..num_words - 1];
+        let full: usize = full_words
+            .chunks(block_words)
+            .map(|block| block.iter().map(|w| w.count_ones() as usize).sum::<usize>())
+            .sum();
+
+        full + self.word(num_words - 1).count_ones() as usize
+    }
+
+    /// Same as [Self::count_ones] but accumulates into a `u64` on all targets, so the count
+    ///  doesn't depend on the width of `usize`.
+    pub fn count_ones_u64(&self) -> u64 {
+        count_ones_wide(self.words())
+    }
+
+    /// Xor of all bits of the bitmap, `true` if an odd number of bits is set.
+    pub fn parity(&self) -> bool {
+        self.fold_xor_u64().count_ones() & 1 == 1
+    }
+
+    /// Xor of all words of the bitmap, bits past `num_bits` are masked out.
+    ///
+    /// A cheap fingerprint for change detection, not a hash. Equal bitmaps always have the same
+    ///  fingerprint but flipping the same bit offset in two different words cancels out.
+    pub fn fold_xor_u64(&self) -> u64 {
+        self.words().fold(0, |acc, w| acc ^ w)
+    }
+
+    /// Returns a bitmap of `indices.len()` bits where bit `k` is the bit of `self` at `indices[k]`.
+    ///
+    /// Out of range indices read as unset, like [Self::is_set].
+    pub fn gather(&self, indices: &[usize]) -> Bitmap {
+        Bitmap::from_fn(indices.len(), |k| self.is_set(indices[k]))
+    }
+
+    /// Inverse of [Self::gather], returns a bitmap of `dst_len` bits where the bit at `indices[k]`
+    ///  is bit `k` of `self` and all other bits are unset.
+    ///
+    /// If a target index appears more than once the later one in `indices` wins. Target indices
+    ///  that are `dst_len` or more are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `indices.len() != self.num_bits()`.
+    pub fn scatter(&self, indices: &[usize], dst_len: usize) -> Bitmap {
+        assert_eq!(indices.len(), self.num_bits);
+
+        let edits = indices
+            .iter()
+            .enumerate()
+            .map(|(k, &index)| (index, self.is_set(k)))
+            .collect::<Vec<_>>();
+
+        let mut out = Bitmap::new(dst_len);
+        out.apply_edits(&edits);
+        out
+    }
+
+    /// Clears all set bits except the ones at given indices.
+    ///
+    /// Indices that are out of bounds or that point to unset bits are ignored. Copies the
+    ///  underlying buffer if it is shared with another bitmap.
+    pub fn retain_indices(&mut self, keep: &[usize]) {
+        let keep = keep
+            .iter()
+            .copied()
+            .filter(|&index| index < self.num_bits)
+            .collect::<Vec<_>>();
+        *self &= &Bitmap::from_indices(self.num_bits, &keep);
+    }
+
+    /// Returns a new bitmap containing the bits that are set in `self` but not in `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn and_not(&self, other: &Bitmap) -> Bitmap {
+        self.zip_words(other, |a, b| a & !b)
+    }
+
+    /// Unsets the bits in `self` that are set in `other`, in place.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn difference_update(&mut self, other: &Bitmap) {
+        self.zip_words_assign(other, |a, b| a & !b);
+    }
+
+    /// Removes the bits that are set in `other` from `self`, same as [Self::difference_update].
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn clear_set_bits_of(&mut self, other: &Bitmap) {
+        self.difference_update(other);
+    }
+
+    /// Sets the bits of `self` to `value` wherever `mask` is set, other bits are left unchanged.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != mask.num_bits()`.
+    pub fn set_where(&mut self, mask: &Bitmap, value: bool) {
+        let value = if value { u64::MAX } else { 0 };
+        self.zip_words_assign(mask, |a, m| (a & !m) | (m & value));
+    }
+
+    /// Bitwise and of `other` into `self` limited to `[start, start + len)`, bits outside of the
+    ///  range are left unchanged.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()` or if given range is outside of the bitmap.
+    pub fn and_assign_in_range(&mut self, other: &Bitmap, start: usize, len: usize) {
+        assert_eq!(self.num_bits, other.num_bits);
+        assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+        if len == 0 {
+            return;
+        }
+
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+        let words = self.words_mut();
+        for (i, mask) in range_word_masks(start, len) {
+            words[i] = (u64::from_le(words[i]) & (other.word(i) | !mask)).to_le();
+        }
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Returns a new bitmap with only the bits of `self` inside any of the given `(start, len)`
+    ///  ranges, same as `self & &Bitmap::from_set_ranges(self.num_bits(), ranges)`.
+    ///
+    /// Ranges don't have to be sorted and can overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is outside of the bitmap.
+    pub fn intersect_with_ranges(&self, ranges: &[(usize, usize)]) -> Bitmap {
+        let mut out = Bitmap::new(self.num_bits);
+        let words = out.words_mut();
+        for &(start, len) in ranges {
+            assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+            for (i, mask) in range_word_masks(start, len) {
+                words[i] = (u64::from_le(words[i]) | (self.word(i) & mask)).to_le();
+            }
+        }
+
+        out
+    }
+
+    /// Number of set bits in `[start, start + len)`
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    pub fn count_ones_in_range(&self, start: usize, len: usize) -> usize {
+        self.range_words(start, len)
+            .map(|(_, w)| w.count_ones() as usize)
+            .sum()
+    }
+
+    /// Number of set bits in each of the given `(start, len)` ranges.
+    ///
+    /// Builds a [PrefixPopcount] once so each range is answered in constant time, which is
+    ///  faster than calling [Self::count_ones_in_range] for many or overlapping ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a range is outside of the bitmap.
+    pub fn count_ones_in_ranges(&self, ranges: &[(usize, usize)]) -> Vec<usize> {
+        let prefix = self.prefix_popcount();
+        ranges
+            .iter()
+            .map(|&(start, len)| prefix.range_count(start, start.checked_add(len).unwrap()))
+            .collect()
+    }
+
+    /// Number of bits in `[start, start + len)` that are set in both `self` and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of either bitmap.
+    pub fn and_count_in_range(&self, other: &Bitmap, start: usize, len: usize) -> usize {
+        let end = start.checked_add(len).unwrap();
+        assert!(end <= self.num_bits && end <= other.num_bits);
+
+        range_word_masks(start, len)
+            .map(|(i, mask)| (self.word(i) & other.word(i) & mask).count_ones() as usize)
+            .sum()
+    }
+
+    /// Number of bits in `[start, start + len)` that are set in `self` but not in `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of either bitmap.
+    pub fn and_not_count_in_range(&self, other: &Bitmap, start: usize, len: usize) -> usize {
+        let end = start.checked_add(len).unwrap();
+        assert!(end <= self.num_bits && end <= other.num_bits);
+
+        range_word_masks(start, len)
+            .map(|(i, mask)| (self.word(i) & !other.word(i) & mask).count_ones() as usize)
+            .sum()
+    }
+
+    /// Number of set bits in `[0, bit_index)`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bit_index > self.num_bits()`.
+    pub fn rank(&self, bit_index: usize) -> usize {
+        self.count_ones_in_range(0, bit_index)
+    }
+
+    /// Number of set bits whose index satisfies `pred`.
+    ///
+    /// `pred` has to be monotone over the index, returning `true` for all indices before some
+    ///  point and `false` after it. The point is found with binary search over `[0, num_bits)`.
+    pub fn partition_point<P: Fn(usize) -> bool>(&self, pred: P) -> usize {
+        let (mut lo, mut hi) = (0, self.num_bits);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if pred(mid) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        self.rank(lo)
+    }
+
+    /// Returns the number of set bits in each consecutive block of `block_bits` bits.
+    ///  The last block is shorter if `block_bits` doesn't divide `self.num_bits()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_bits` is zero.
+    pub fn popcount_per_block(&self, block_bits: usize) -> Vec<usize> {
+        assert!(block_bits > 0);
+
+        (0..self.num_bits)
+            .step_by(block_bits)
+            .map(|start| self.count_ones_in_range(start, block_bits.min(self.num_bits - start)))
+            .collect()
+    }
+
+    /// Returns a bitmap of `self.num_bits().div_ceil(factor)` bits where bit `k` is set if any of
+    ///  the bits in `[k * factor, (k + 1) * factor)` is set. The last group is shorter if `factor`
+    ///  doesn't divide `self.num_bits()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn downsample_or(&self, factor: usize) -> Bitmap {
+        self.downsample(factor, |count, _| count > 0)
+    }
+
+    /// Returns a bitmap of `self.num_bits() * factor` bits where each bit `i` of `self` is
+    ///  repeated in `[i * factor, (i + 1) * factor)`.
+    ///
+    /// Fills one range per run of set bits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of bits overflows.
+    pub fn upsample(&self, factor: usize) -> Bitmap {
+        let mut out = Bitmap::new(self.num_bits.checked_mul(factor).unwrap());
+        for (start, len) in self.set_ranges() {
+            out.set_range(start * factor, len * factor, true);
+        }
+        out
+    }
+
+    /// Same as [Self::downsample_or] but bit `k` is set only if all bits of its group are set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is zero.
+    pub fn downsample_and(&self, factor: usize) -> Bitmap {
+        self.downsample(factor, |count, len| count == len)
+    }
+
+    /// Treats the bitmap as a row-major matrix with `width` bits per row and returns the xor of
+    ///  all rows, i.e. the parity of each column.
+    ///
+    /// The last row can be shorter than `width`, its missing bits are treated as zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero.
+    pub fn xor_rows(&self, width: usize) -> Bitmap {
+        assert!(width > 0);
+
+        let mut parity = Bitmap::new(width);
+        let words = parity.words_mut();
+
+        for row_start in (0..self.num_bits).step_by(width) {
+            let row_len = width.min(self.num_bits - row_start);
+            for (j, word) in words.iter_mut().enumerate() {
+                let offset = j * 64;
+                if offset >= row_len {
+                    break;
+                }
+                let bits = self.get_bits_u64(row_start + offset, (row_len - offset).min(64));
+                *word ^= bits.to_le();
+            }
+        }
+
+        parity
+    }
+
+    /// Counts the set bits at each residue class of `stride`, element `r` of the returned vector
+    ///  is the number of set bits with `index % stride == r`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stride` is zero.
+    pub fn residue_histogram(&self, stride: usize) -> Vec<usize> {
+        assert!(stride > 0);
+
+        let mut histogram = vec![0; stride];
+        for index in self.iter_set_bits() {
+            histogram[index % stride] += 1;
+        }
+        histogram
+    }
+
+    /// Renders the bitmap as a single line of `width` characters for debugging.
+    ///
+    /// Each character covers an equal share of the bits and is shaded by the share of set bits in
+    ///  it, from `' '` for none to `'█'` for all. Characters that cover no bits, which happens
+    ///  when `width > self.num_bits()`, are rendered as `' '`.
+    ///
+    /// The shares differ by one bit when `width` doesn't divide `self.num_bits()`, so the columns
+    ///  are counted one by one instead of with the fixed size blocks of [Self::popcount_per_block],
+    ///  which would leave trailing columns empty.
+    pub fn to_ascii_art(&self, width: usize) -> String {
+        const SHADES: [char; 5] = [' ', '░', '▒', '▓', '█'];
+
+        (0..width)
+            .map(|column| {
+                let start = mul_div(column, self.num_bits, width);
+                let end = mul_div(column + 1, self.num_bits, width);
+                let count = self.count_ones_in_range(start, end - start);
+
+                if count == 0 {
+                    SHADES[0]
+                } else if count == end - start {
+                    SHADES[4]
+                } else {
+                    // Partially set blocks map to the three middle shades
+                    SHADES[1 + (count * 3 / (end - start)).min(2)]
+                }
+            })
+            .collect()
+    }
+
+    /// Number of bits that are set in both bitmaps, without materializing `self & other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn intersection_count(&self, other: &Bitmap) -> usize {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        let num_full_words = self.num_bits / 64;
+        let full_count = unsafe {
+            crate::compute::and_count(
+                self.buf.as_ptr() as *const u64,
+                other.buf.as_ptr() as *const u64,
+                num_full_words,
+            )
+        };
+
+        if num_full_words == self.num_words() {
+            full_count
+        } else {
+            full_count
+                + (self.word(num_full_words) & other.word(num_full_words)).count_ones() as usize
+        }
+    }
+
+    /// Number of set bits in the bitwise or of the bitmaps, see [Self::boolean_fn_count].
+    pub fn union_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| a | b)
+    }
+
+    /// Estimates [Self::union_count] by reading only `sample_words` evenly spaced words of both
+    ///  bitmaps and scaling the sampled count up to `num_bits`.
+    ///
+    /// If the set bits are spread uniformly with density `p` in the union, the relative standard
+    ///  error of the estimate is about `sqrt((1 - p) / (p * 64 * sample_words))`. The sampled words
+    ///  are at a fixed stride, so bitmaps with clustered or periodic bits can be estimated far off.
+    ///  Returns the exact count if `sample_words` covers all words of the bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()` or if `sample_words` is zero.
+    pub fn estimate_union_count(&self, other: &Bitmap, sample_words: usize) -> usize {
+        assert_eq!(self.num_bits, other.num_bits);
+        assert!(sample_words > 0);
+
+        let num_words = self.num_words();
+        if sample_words >= num_words {
+            return self.union_count(other);
+        }
+
+        let mut sampled_bits = 0;
+        let mut sampled_count = 0;
+        for i in 0..sample_words {
+            let word_index = mul_div(i, num_words, sample_words);
+            sampled_bits += if word_index + 1 == num_words {
+                self.num_bits - word_index * 64
+            } else {
+                64
+            };
+            sampled_count += (self.word(word_index) | other.word(word_index)).count_ones() as usize;
+        }
+
+        (sampled_count as f64 * self.num_bits as f64 / sampled_bits as f64).round() as usize
+    }
+
+    /// Number of set bits in `self & !other`, see [Self::boolean_fn_count].
+    pub fn difference_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| a & !b)
+    }
+
+    /// Number of set bits in `self | !other`, see [Self::boolean_fn_count].
+    pub fn or_not_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| a | !b)
+    }
+
+    /// Number of set bits in `!(self & other)`, see [Self::boolean_fn_count].
+    pub fn nand_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| !(a & b))
+    }
+
+    /// Number of set bits in `!(self | other)`, see [Self::boolean_fn_count].
+    pub fn nor_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| !(a | b))
+    }
+
+    /// Number of set bits in `!(self ^ other)`, see [Self::boolean_fn_count].
+    pub fn xnor_count(&self, other: &Bitmap) -> usize {
+        self.boolean_fn_count(other, |a, b| !(a ^ b))
+    }
+
+    /// Number of set bits in the bitmap produced by applying `f` to each word pair of the bitmaps,
+    ///  without materializing it.
+    ///
+    /// Bits past `num_bits` in the last word of the result are ignored, so `f` can set them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn boolean_fn_count<F: Fn(u64, u64) -> u64>(&self, other: &Bitmap, f: F) -> usize {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        let num_words = self.num_words();
+        let mask = self.last_word_mask();
+
+        self.words()
+            .zip(other.words())
+            .enumerate()
+            .map(|(i, (a, b))| {
+                let word = f(a, b);
+                let word = if i + 1 == num_words {
+                    word & mask
+                } else {
+                    word
+                };
+                word.count_ones() as usize
+            })
+            .sum()
+    }
+
+    /// Calls `f(word_index, self_word, other_word)` for each word pair of the bitmaps in ascending
+    /// order, bits past `num_bits` in the last word are masked out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn for_each_word_pair<F: FnMut(usize, u64, u64)>(&self, other: &Bitmap, mut f: F) {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        for (i, (a, b)) in self.words().zip(other.words()).enumerate() {
+            f(i, a, b);
+        }
+    }
+
+    /// Returns the number of set bits before each word boundary.
+    ///
+    /// Element `i` is the number of set bits in `[0, i * 64)`, the returned vector has
+    ///  `self.num_bits().div_ceil(64) + 1` elements.
+    pub fn prefix_counts(&self) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(self.num_words() + 1);
+        let mut total = 0;
+        counts.push(0);
+        for word in self.words() {
+            total += word.count_ones() as usize;
+            counts.push(total);
+        }
+        counts
+    }
+
+    /// Same as [Self::prefix_counts] with boundaries every `block_bits` bits instead of every word.
+    ///
+    /// Element `i` is the number of set bits in `[0, min(i * block_bits, num_bits))`, so
+    ///  `self.rank(i)` is `counts[i / block_bits]` plus the set bits of the block before `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_bits` isn't a power of two.
+    pub fn block_popcounts(&self, block_bits: usize) -> Vec<usize> {
+        assert!(
+            block_bits.is_power_of_two(),
+            "block_bits must be a power of two, got {}",
+            block_bits
+        );
+
+        let mut counts = Vec::with_capacity(self.num_bits.div_ceil(block_bits) + 1);
+        let mut total = 0;
+        counts.push(0);
+        for count in self.popcount_per_block(block_bits) {
+            total += count;
+            counts.push(total);
+        }
+        counts
+    }
+
+    /// Builds a [PrefixPopcount] for answering range count queries in constant time.
+    pub fn prefix_popcount(&self) -> PrefixPopcount {
+        PrefixPopcount::new(self.clone())
+    }
+
+    /// Writes `self & other` into `dst`, reusing the buffer of `dst` if it isn't shared and is big enough.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn and_into(&self, other: &Bitmap, dst: &mut Bitmap) {
+        self.zip_words_into(other, dst, |a, b| a & b);
+    }
+
+    /// Writes `self | other` into `dst`, reusing the buffer of `dst` if it isn't shared and is big enough.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn or_into(&self, other: &Bitmap, dst: &mut Bitmap) {
+        self.zip_words_into(other, dst, |a, b| a | b);
+    }
+
+    /// Writes `self ^ other` into `dst`, reusing the buffer of `dst` if it isn't shared and is big enough.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn xor_into(&self, other: &Bitmap, dst: &mut Bitmap) {
+        self.zip_words_into(other, dst, |a, b| a ^ b);
+    }
+
+    /// Number of set bits in the bitwise or of all given bitmaps, same as
+    ///  `Bitmap::union_all(bitmaps).count_ones()` without allocating the union.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps don't all have the same number of bits.
+    pub fn distinct_set_bits(bitmaps: &[Bitmap]) -> usize {
+        let Some(first) = bitmaps.first() else {
+            return 0;
+        };
+        for bitmap in bitmaps {
+            assert_eq!(bitmap.num_bits, first.num_bits);
+        }
+
+        (0..first.num_words())
+            .map(|i| {
+                let word = bitmaps.iter().fold(0, |acc, bitmap| acc | bitmap.word(i));
+                word.count_ones() as usize
+            })
+            .sum()
+    }
+
+    /// Iterates over the distinct set bit indices of all given bitmaps in ascending order, same as
+    /// iterating `Bitmap::union_all(bitmaps)` without allocating the union.
+    ///
+    /// Does a k-way merge of the [Self::iter_set_bits] iterators of the bitmaps, they don't have to
+    /// have the same number of bits.
+    pub fn merge_set_bits(bitmaps: &[Bitmap]) -> impl Iterator<Item = usize> + '_ {
+        let mut iters = bitmaps
+            .iter()
+            .map(|bitmap| bitmap.iter_set_bits())
+            .collect::<Vec<_>>();
+        let mut heap = iters
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, iter)| Some(Reverse((iter.next()?, i))))
+            .collect::<BinaryHeap<_>>();
+        let mut last = None;
+
+        std::iter::from_fn(move || {
+            while let Some(Reverse((pos, i))) = heap.pop() {
+                if let Some(next) = iters[i].next() {
+                    heap.push(Reverse((next, i)));
+                }
+                if last != Some(pos) {
+                    last = Some(pos);
+                    return Some(pos);
+                }
+            }
+            None
+        })
+    }
+
+    /// Returns the bitwise or of all given bitmaps, an empty bitmap if there are none.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps don't all have the same number of bits.
+    pub fn union_all(bitmaps: &[Bitmap]) -> Bitmap {
+        let Some((first, rest)) = bitmaps.split_first() else {
+            return Bitmap::new(0);
+        };
+
+        let mut out = first.clone();
+        out.or_with_iter(rest);
+        out
+    }
+
+    /// Returns the bitmap where each word is `f` applied to the words at the same index of all
+    ///  given bitmaps, in the order of `bitmaps`. An empty bitmap if there are none.
+    ///
+    /// Bits past `num_bits` in the last word of the result are zeroed, so `f` can set them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps don't all have the same number of bits.
+    pub fn combine_n<F: Fn(&[u64]) -> u64>(bitmaps: &[Bitmap], f: F) -> Bitmap {
+        let Some(first) = bitmaps.first() else {
+            return Bitmap::new(0);
+        };
+        for bitmap in bitmaps {
+            assert_eq!(bitmap.num_bits, first.num_bits);
+        }
+
+        // Reused for every word so there is a single allocation
+        let mut words = vec![0; bitmaps.len()];
+        Bitmap::from_word_fn(first.num_bits, |i| {
+            for (dst, bitmap) in words.iter_mut().zip(bitmaps) {
+                *dst = bitmap.word(i);
+            }
+            f(&words)
+        })
+    }
+
+    /// Returns the bitwise majority of three bitmaps, a bit is set if it is set in at least two of
+    ///  them. Same as `combine_n` with `(a & b) | (a & c) | (b & c)` in a single pass.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bitmaps don't all have the same number of bits.
+    pub fn majority3(a: &Bitmap, b: &Bitmap, c: &Bitmap) -> Bitmap {
+        assert_eq!(a.num_bits, b.num_bits);
+        assert_eq!(a.num_bits, c.num_bits);
+
+        Bitmap::from_word_fn(a.num_bits, |i| {
+            let (a, b, c) = (a.word(i), b.word(i), c.word(i));
+            (a & b) | (a & c) | (b & c)
+        })
+    }
+
+    /// Bitwise or of each bitmap in `iter` into `self`, without collecting the bitmaps first.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the bitmaps doesn't have `self.num_bits()` bits.
+    pub fn or_with_iter<'a, I: IntoIterator<Item = &'a Bitmap>>(&mut self, iter: I) {
+        for other in iter {
+            *self |= other;
+        }
+    }
+
+    /// Returns the bitwise or of the bitmaps, the shorter one is extended with zeros.
+    ///
+    /// The result is `max(self.num_bits(), other.num_bits())` bits long.
+    pub fn union_extend(&self, other: &Bitmap) -> Bitmap {
+        let (long, short) = if self.num_bits >= other.num_bits {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let short_words = short.num_words();
+
+        Bitmap::from_word_fn(long.num_bits, |i| {
+            let word = long.word(i);
+            if i < short_words {
+                word | short.word(i)
+            } else {
+                word
+            }
+        })
+    }
+
+    /// Returns the bitwise and of the bitmaps, bits past the end of the shorter one are dropped.
+    ///
+    /// The result is `min(self.num_bits(), other.num_bits())` bits long.
+    pub fn intersect_extend(&self, other: &Bitmap) -> Bitmap {
+        let num_bits = self.num_bits.min(other.num_bits);
+
+        Bitmap::from_word_fn(num_bits, |i| self.word(i) & other.word(i))
+    }
+
+    /// Returns a bitmap of `2 * n` bits where the bit at `2 * i` is bit `i` of `self` and the bit at
+    ///  `2 * i + 1` is bit `i` of `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    pub fn interleave(&self, other: &Bitmap) -> Bitmap {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        Bitmap::from_word_fn(self.num_bits * 2, |i| {
+            let shift = 32 * (i % 2);
+            let a = (self.word(i / 2) >> shift) as u32;
+            let b = (other.word(i / 2) >> shift) as u32;
+            spread_bits(a) | (spread_bits(b) << 1)
+        })
+    }
+
+    /// Inverse of [Self::interleave], returns the even bits and the odd bits as two bitmaps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits()` is odd.
+    pub fn deinterleave(&self) -> (Bitmap, Bitmap) {
+        assert!(
+            self.num_bits.is_multiple_of(2),
+            "can't deinterleave an odd number of bits"
+        );
+
+        let num_bits = self.num_bits / 2;
+        let num_words = self.num_words();
+        let pair = |i: usize| {
+            let low = self.word(2 * i);
+            let high = if 2 * i + 1 < num_words {
+                self.word(2 * i + 1)
+            } else {
+                0
+            };
+            (low, high)
+        };
+
+        let even = Bitmap::from_word_fn(num_bits, |i| {
+            let (low, high) = pair(i);
+            u64::from(compact_bits(low)) | (u64::from(compact_bits(high)) << 32)
+        });
+        let odd = Bitmap::from_word_fn(num_bits, |i| {
+            let (low, high) = pair(i);
+            u64::from(compact_bits(low >> 1)) | (u64::from(compact_bits(high >> 1)) << 32)
+        });
+
+        (even, odd)
+    }
+
+    /// Returns if the bitmaps are equal when the shorter one is extended with zeros.
+    pub fn eq_ignoring_length(&self, other: &Bitmap) -> bool {
+        let (short, long) = if self.num_bits <= other.num_bits {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        // Masked words of `short` hold zeros past its length, same as the implicit extension
+        long.words()
+            .zip(short.words().chain(std::iter::repeat(0)))
+            .all(|(a, b)| a == b)
+    }
+
+    /// Returns if the `len` bits of `self` starting at `self_start` are equal to the `len` bits of
+    ///  `other` starting at `other_start`, without slicing either bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either range is outside of its bitmap.
+    pub fn range_eq(
+        &self,
+        self_start: usize,
+        other: &Bitmap,
+        other_start: usize,
+        len: usize,
+    ) -> bool {
+        assert!(self_start.checked_add(len).unwrap() <= self.num_bits);
+        assert!(other_start.checked_add(len).unwrap() <= other.num_bits);
+
+        (0..len).step_by(64).all(|offset| {
+            let chunk_len = (len - offset).min(64);
+            self.get_bits_u64(self_start + offset, chunk_len)
+                == other.get_bits_u64(other_start + offset, chunk_len)
+        })
+    }
+
+    /// Returns the first offset where the bits of `self` are equal to all bits of `pattern`.
+    ///
+    /// An empty pattern is found at offset zero. Each offset is compared with [Self::range_eq], so
+    ///  this takes `O(self.num_bits() * pattern.num_bits() / 64)` time in the worst case.
+    pub fn find_pattern(&self, pattern: &Bitmap) -> Option<usize> {
+        let last_start = self.num_bits.checked_sub(pattern.num_bits)?;
+        (0..=last_start).find(|&start| self.range_eq(start, pattern, 0, pattern.num_bits))
+    }
+
+    /// Panics with a description of the problem if [Self::validate] fails.
+    ///
+    /// Called at the end of operations that produce a fresh buffer or clear all padding bits.
+    ///  In place operations call [Self::assert_padding_kept] instead, see there.
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn assert_invariants(&self) {
+        if let Err(err) = self.validate() {
+            panic!("bitmap invariant violated: {}", err);
+        }
+    }
+
+    /// Bits of the last word of the buffer that are past `num_bits`, unmasked.
+    #[cfg(debug_assertions)]
+    fn last_word_padding(&self) -> u64 {
+        match self.as_word_slice().last() {
+            Some(&last) => u64::from_le(last) & !self.last_word_mask(),
+            None => 0,
+        }
+    }
+
+    /// Panics if the buffer can't hold `num_bits`, or if a bit past `num_bits` is set that wasn't
+    ///  set in `padding`, the [Self::last_word_padding] from before the operation.
+    ///
+    /// Called at the end of operations that write in place without changing `num_bits`. Bitmaps
+    ///  that share a buffer with a longer bitmap, or that were created with [Self::from_buf], can
+    ///  legitimately have set padding bits, so these only check that they didn't set any new
+    ///  ones. Only the last word is checked since in place writes never go past it.
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    fn assert_padding_kept(&self, padding: u64) {
+        if self.num_bits.div_ceil(8) > self.buf.len() {
+            let err = ValidationError::BufferTooSmall {
+                num_bits: self.num_bits,
+                buffer_len: self.buf.len(),
+            };
+            panic!("bitmap invariant violated: {}", err);
+        }
+
+        let set = self.last_word_padding() & !padding;
+        if set != 0 {
+            panic!(
+                "bitmap invariant violated: {} padding bits were set",
+                set.count_ones()
+            );
+        }
+    }
+
+    /// Number of 64 bit words needed to hold `num_bits`
+    #[inline(always)]
+    fn num_words(&self) -> usize {
+        self.num_bits.div_ceil(64)
+    }
+
+    /// Mask of the valid bits in the last word
+    #[inline(always)]
+    fn last_word_mask(&self) -> u64 {
+        match self.num_bits % 64 {
+            0 => u64::MAX,
+            rem => (1 << rem) - 1,
+        }
+    }
+
+    /// Reads the word at given index, bits past `num_bits` are masked out.
+    ///
+    /// The buffer is padded to 64 bytes so reading the whole last word is fine.
+    #[inline(always)]
+    pub(crate) fn word(&self, word_index: usize) -> u64 {
+        debug_assert!(word_index < self.num_words());
+
+        let word = unsafe { u64::from_le(*(self.buf.as_ptr() as *const u64).add(word_index)) };
+
+        if word_index + 1 == self.num_words() {
+            word & self.last_word_mask()
+        } else {
+            word
+        }
+    }
+
+    /// Iterates over the words overlapping `[start, start + len)` paired with their index.
+    ///  Bits outside of the range are masked out, the words are not re-aligned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if given range is outside of the bitmap.
+    fn range_words(&self, start: usize, len: usize) -> impl Iterator<Item = (usize, u64)> + '_ {
+        assert!(start.checked_add(len).unwrap() <= self.num_bits);
+
+        range_word_masks(start, len).map(move |(i, mask)| (i, self.word(i) & mask))
+    }
+
+    /// Index of the first bit at or after `from` that is equal to `value`, `num_bits` if there is none.
+    fn next_with_value(&self, from: usize, value: bool) -> usize {
+        if from >= self.num_bits {
+            return self.num_bits;
+        }
+
+        let flip = if value { 0 } else { u64::MAX };
+
+        let mut word_index = from / 64;
+        let mut word = (self.word(word_index) ^ flip) & (u64::MAX << (from % 64));
+
+        loop {
+            if word != 0 {
+                let index = word_index * 64 + word.trailing_zeros() as usize;
+                return index.min(self.num_bits);
+            }
+
+            word_index += 1;
+            if word_index >= self.num_words() {
+                return self.num_bits;
+            }
+            word = self.word(word_index) ^ flip;
+        }
+    }
+
+    /// Downsamples the bitmap into groups of `factor` bits, `f` gets the number of set bits and
+    ///  the length of each group.
+    fn downsample<F: Fn(usize, usize) -> bool>(&self, factor: usize, f: F) -> Bitmap {
+        assert!(factor > 0);
+
+        Bitmap::from_fn(self.num_bits.div_ceil(factor), |k| {
+            let start = k * factor;
+            let len = factor.min(self.num_bits - start);
+            f(self.count_ones_in_range(start, len), len)
+        })
+    }
+
+    /// Word at `word_index` of the bitmap shifted left by `n` bits, bits shifted in are zero.
+    ///  Bits past `num_bits` in the last word aren't masked out.
+    fn shifted_left_word(&self, word_index: usize, n: usize) -> u64 {
+        let (word_shift, bit_shift) = (n / 64, n % 64);
+        let Some(src) = word_index.checked_sub(word_shift) else {
+            return 0;
+        };
+
+        let mut word = self.word(src) << bit_shift;
+        if bit_shift != 0 && src > 0 {
+            word |= self.word(src - 1) >> (64 - bit_shift);
+        }
+        word
+    }
+
+    /// Word at `word_index` of the bitmap shifted right by `n` bits, bits shifted in are zero.
+    fn shifted_right_word(&self, word_index: usize, n: usize) -> u64 {
+        let (word_shift, bit_shift) = (n / 64, n % 64);
+        let num_words = self.num_words();
+        let src = match word_index.checked_add(word_shift) {
+            Some(src) if src < num_words => src,
+            _ => return 0,
+        };
+
+        let mut word = self.word(src) >> bit_shift;
+        if bit_shift != 0 && src + 1 < num_words {
+            word |= self.word(src + 1) << (64 - bit_shift);
+        }
+        word
+    }
+
+    /// Iterates over the words of the bitmap, bits past `num_bits` are masked out.
+    fn words(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.num_words()).map(|i| self.word(i))
+    }
+
+    /// Returns the underlying words as little endian encoded, mutable slice.
+    ///
+    /// Copies the underlying buffer if it is shared with another bitmap.
+    fn words_mut(&mut self) -> &mut [u64] {
+        let num_words = self.num_words();
+        if num_words == 0 {
+            return &mut [];
+        }
+
+        let buf = Arc::make_mut(&mut self.buf);
+        unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u64, num_words) }
+    }
+
+    /// Makes sure the buffer is at least `num_bytes` long, moving the bits into a new buffer if it isn't.
+    fn reserve_bytes(&mut self, num_bytes: usize) {
+        if num_bytes <= self.buf.len() {
+            return;
+        }
+
+        let valid_bytes = self.num_bits.div_ceil(8);
+        let mut buf = Buffer::new(num_bytes);
+        buf.as_mut_slice()[..valid_bytes].copy_from_slice(self.as_byte_slice());
+
+        self.buf = Arc::new(buf);
+        self.mask_last_word();
+    }
+
+    /// Same as [Self::reserve_bytes] but at least doubles the buffer when it has to grow, so
+    ///  repeatedly growing by small amounts is amortized.
+    fn reserve_bits_amortized(&mut self, num_bits: usize) {
+        let num_bytes = num_bits.div_ceil(8);
+        if num_bytes > self.buf.len() {
+            self.reserve_bytes(num_bytes.max(self.buf.len() * 2));
+        }
+    }
+
+    /// Zeroes the bits past `num_bits` in the last word.
+    fn mask_last_word(&mut self) {
+        let mask = self.last_word_mask();
+        if let Some(last) = self.words_mut().last_mut() {
+            *last = (u64::from_le(*last) & mask).to_le();
+        }
+    }
+
+    /// Applies `f` to each word pair of `self` and `other`, writing the result into `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    fn zip_words_assign(&mut self, other: &Bitmap, f: impl Fn(u64, u64) -> u64) {
+        assert_eq!(self.num_bits, other.num_bits);
+        #[cfg(debug_assertions)]
+        let padding = self.last_word_padding();
+
+        for (dst, src) in self.words_mut().iter_mut().zip(other.words()) {
+            *dst = f(u64::from_le(*dst), src).to_le();
+        }
+
+        self.mask_last_word();
+        #[cfg(debug_assertions)]
+        self.assert_padding_kept(padding);
+    }
+
+    /// Applies `f` to each word pair of `self` and `other`, writing the result into `dst`.
+    ///
+    /// Reuses the buffer of `dst` if it isn't shared and is big enough, allocates a new one otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    fn zip_words_into(&self, other: &Bitmap, dst: &mut Bitmap, f: impl Fn(u64, u64) -> u64) {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        let reusable =
+            Arc::get_mut(&mut dst.buf).is_some_and(|buf| buf.len() >= self.num_bits.div_ceil(8));
+        if !reusable {
+            *dst = self.zip_words(other, f);
+            return;
+        }
+
+        dst.num_bits = self.num_bits;
+        for ((out, a), b) in dst
+            .words_mut()
+            .iter_mut()
+            .zip(self.words())
+            .zip(other.words())
+        {
+            *out = f(a, b).to_le();
+        }
+        dst.clear_padding_bits();
+    }
+
+    /// Returns the set ranges of the words produced by applying `f` to each word pair of `self` and `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    fn zip_set_ranges(&self, other: &Bitmap, f: impl Fn(u64, u64) -> u64) -> Vec<(usize, usize)> {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        let words = self.words().zip(other.words()).map(|(a, b)| f(a, b));
+
+        let mut ranges = Vec::new();
+        crate::compute::push_set_ranges(words, self.num_bits, &mut ranges);
+        ranges
+    }
+
+    /// Applies `f` to each word pair of `self` and `other`, writing the result into a new bitmap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != other.num_bits()`.
+    fn zip_words(&self, other: &Bitmap, f: impl Fn(u64, u64) -> u64) -> Bitmap {
+        assert_eq!(self.num_bits, other.num_bits);
+
+        let mut out = Bitmap::new(self.num_bits);
+
+        for ((dst, a), b) in out
+            .words_mut()
+            .iter_mut()
+            .zip(self.words())
+            .zip(other.words())
+        {
+            *dst = f(a, b).to_le();
+        }
+
+        out.mask_last_word();
+        #[cfg(debug_assertions)]
+        out.assert_invariants();
+
+        out
+    }
+}
+
+impl PartialEq for Bitmap {
+    fn eq(&self, other: &Self) -> bool {
+        if self.num_bits != other.num_bits {
+            return false;
+        }
+
+        let num_full_words = self.num_bits / 64;
+        let full_words_eq = unsafe {
+            crate::compute::eq(self.buf.as_ptr(), other.buf.as_ptr(), num_full_words * 8)
+        };
+
+        full_words_eq
+            && (num_full_words == self.num_words()
+                || self.word(num_full_words) == other.word(num_full_words))
+    }
+}
+
+impl Eq for Bitmap {}
+
+impl fmt::Debug for Bitmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = (0..self.num_bits)
+            .map(|i| {
+                if unsafe { self.get_unchecked(i) } {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect::<String>();
+
+        f.debug_struct("Bitmap")
+            .field("num_bits", &self.num_bits)
+            .field("bits", &bits)
+            .finish()
+    }
+}
+
+impl BitAnd for &Bitmap {
+    type Output = Bitmap;
+
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitand(self, rhs: &Bitmap) -> Bitmap {
+        self.zip_words(rhs, |a, b| a & b)
+    }
+}
+
+impl BitOr for &Bitmap {
+    type Output = Bitmap;
+
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitor(self, rhs: &Bitmap) -> Bitmap {
+        self.zip_words(rhs, |a, b| a | b)
+    }
+}
+
+impl BitXor for &Bitmap {
+    type Output = Bitmap;
+
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitxor(self, rhs: &Bitmap) -> Bitmap {
+        self.zip_words(rhs, |a, b| a ^ b)
+    }
+}
+
+impl Not for &Bitmap {
+    type Output = Bitmap;
+
+    fn not(self) -> Bitmap {
+        self.zip_words(self, |a, _| !a)
+    }
+}
+
+impl BitAndAssign<&Bitmap> for Bitmap {
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitand_assign(&mut self, rhs: &Bitmap) {
+        self.zip_words_assign(rhs, |a, b| a & b);
+    }
+}
+
+impl BitOrAssign<&Bitmap> for Bitmap {
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitor_assign(&mut self, rhs: &Bitmap) {
+        self.zip_words_assign(rhs, |a, b| a | b);
+    }
+}
+
+impl BitXorAssign<&Bitmap> for Bitmap {
+    /// # Panics
+    ///
+    /// Panics if `self.num_bits() != rhs.num_bits()`.
+    fn bitxor_assign(&mut self, rhs: &Bitmap) {
+        self.zip_words_assign(rhs, |a, b| a ^ b);
+    }
+}
+
+/// Iterates over the positions of set bits in `word` in ascending order, offset by `base`
+#[inline(always)]
+pub(crate) fn set_bit_positions(mut word: u64, base: usize) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if word == 0 {
+            return None;
+        }
+        let bit = word.trailing_zeros() as usize;
+        word &= word - 1;
+        Some(base + bit)
+    })
+}
+
+/// Iterates over the word indices overlapping `[start, start + len)`, paired with the mask of
+///  the bits inside the range for each word.
+fn range_word_masks(start: usize, len: usize) -> impl Iterator<Item = (usize, u64)> {
+    let end = start + len;
+    let first_word = start / 64;
+    let end_word = if len == 0 {
+        first_word
+    } else {
+        end.div_ceil(64)
+    };
+
+    (first_word..end_word).map(move |i| {
+        let mut mask = u64::MAX;
+        if i == first_word {
+            mask &= u64::MAX << (start % 64);
+        }
+        if i + 1 == end_word && !end.is_multiple_of(64) {
+            mask &= (1 << (end % 64)) - 1;
+        }
+        (i, mask)
+    })
+}
+
+/// Packs `bools` into the first `bools.len().div_ceil(8)` bytes of `dst`, overwriting them.
+fn pack_bools_into(bools: &[bool], dst: &mut [u8]) {
+    let num_bits = bools.len();
+    assert!(dst.len() >= num_bits.div_ceil(8));
+
+    // Compiler vectorizes this
+    unsafe {
+        let mut bools_ptr = bools.as_ptr() as *const u8;
+        let mut buf_ptr = dst.as_mut_ptr();
+        for _ in 0..num_bits / 8 {
+            let byte = *bools_ptr
+                | *bools_ptr.add(1) << 1
+                | *bools_ptr.add(2) << 2
+                | *bools_ptr.add(3) << 3
+                | *bools_ptr.add(4) << 4
+                | *bools_ptr.add(5) << 5
+                | *bools_ptr.add(6) << 6
+                | *bools_ptr.add(7) << 7;
+
+            *buf_ptr = byte;
+
+            bools_ptr = bools_ptr.add(8);
+            buf_ptr = buf_ptr.add(1);
+        }
+
+        if !num_bits.is_multiple_of(8) {
+            let mut byte = 0;
+            for (shift, _) in (0..num_bits % 8).enumerate() {
+                byte |= *bools_ptr << shift;
+                bools_ptr = bools_ptr.add(1);
+            }
+            *buf_ptr = byte;
+        }
+    }
+}
+
+/// Allocates a zeroed buffer that can hold `num_bits`, returning an error instead of panicking if
+///  `num_bits` is over [MAX_BITS].
+///
+/// Panics if the memory can't be allocated. The padded buffer size of [MAX_BITS] bits is far below
+///  `isize::MAX` bytes, so it can't overflow in [Buffer::new].
+fn try_new_buffer(num_bits: usize) -> Result<Buffer, BuildError> {
+    if num_bits > MAX_BITS {
+        return Err(BuildError::CapacityOverflow { num_bits });
+    }
+
+    Ok(Buffer::new(num_bits.div_ceil(8)))
+}
+
+/// Panics with a clear message before attempting to allocate if `num_bits` is over [MAX_BITS].
+#[inline(always)]
+#[track_caller]
+fn assert_num_bits(num_bits: usize) {
+    assert!(
+        num_bits <= MAX_BITS,
+        "bitmap too large: requested {} bits",
+        num_bits
+    );
+}
+
+/// Moves bit `i` of `value` to bit `2 * i` of the output, leaving the odd bits zero.
+#[inline(always)]
+fn spread_bits(value: u32) -> u64 {
+    let mut x = u64::from(value);
+    x = (x | (x << 16)) & 0x0000_FFFF_0000_FFFF;
+    x = (x | (x << 8)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x << 4)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x << 2)) & 0x3333_3333_3333_3333;
+    (x | (x << 1)) & 0x5555_5555_5555_5555
+}
+
+/// Inverse of [spread_bits], gathers the even bits of `value` into the output.
+#[inline(always)]
+fn compact_bits(value: u64) -> u32 {
+    let mut x = value & 0x5555_5555_5555_5555;
+    x = (x | (x >> 1)) & 0x3333_3333_3333_3333;
+    x = (x | (x >> 2)) & 0x0F0F_0F0F_0F0F_0F0F;
+    x = (x | (x >> 4)) & 0x00FF_00FF_00FF_00FF;
+    x = (x | (x >> 8)) & 0x0000_FFFF_0000_FFFF;
+    (x | (x >> 16)) as u32
+}
+
+/// Sums the set bits of `words` into a `u64` accumulator.
+#[inline(always)]
+fn count_ones_wide(words: impl Iterator<Item = u64>) -> u64 {
+    words.map(|w| u64::from(w.count_ones())).sum()
+}
+
+/// Computes `a * b / c` without overflowing the intermediate product, the result has to fit in a
+///  `usize`.
+#[inline(always)]
+fn mul_div(a: usize, b: usize, c: usize) -> usize {
+    (a as u128 * b as u128 / c as u128) as usize
+}
+
+/// Mask with the low `len` bits set, `len` should be at most 64
+#[inline(always)]
+fn low_bits_mask(len: usize) -> u64 {
+    if len >= 64 {
+        u64::MAX
+    } else {
+        (1 << len) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    fn run_test(bools: &[bool]) {
+        let bitmap = Bitmap::from_bools(bools);
+
+        for (i, &b) in bools.iter().enumerate() {
+            assert_eq!(b, bitmap.get(i).unwrap());
+        }
+
+        let offset = bitmap.num_bits() / 2;
+        let new_len = bitmap.num_bits().saturating_sub(offset);
+        let shifted_bitmap = bitmap.slice(offset, new_len);
+
+        for i in 0..new_len {
+            assert_eq!(
+                bitmap.get(i + offset).unwrap(),
+                shifted_bitmap.get(i).unwrap(),
+                "failed at idx {}, len is: {}",
+                i,
+                bools.len()
+            );
+        }
+    }
+
+    fn generate(len: usize) -> Vec<bool> {
+        let mut buf = Vec::with_capacity(len);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        for _ in 0..len {
+            buf.push(rng.gen_bool(0.5));
+        }
+
+        buf
+    }
+
+    #[test]
+    fn test_difference_update() {
+        let a = Bitmap::from_bools(&generate(1000));
+        let mut b_bools = generate(1003);
+        b_bools.truncate(1000);
+        b_bools.reverse();
+        let b = Bitmap::from_bools(&b_bools);
+
+        let expected = a.and_not(&b);
+        for i in 0..1000 {
+            assert_eq!(
+                expected.get(i),
+                Some(a.get(i).unwrap() && !b.get(i).unwrap())
+            );
+        }
+
+        let mut updated = a.clone();
+        updated.difference_update(&b);
+        assert_eq!(updated, expected);
+        assert_eq!(a, Bitmap::from_bools(&generate(1000)));
+    }
+
+    #[test]
+    fn test_clear_set_bits_of() {
+        let a = Bitmap::from_bools(&generate(1000));
+        let b = Bitmap::from_bools(&generate(1003)[3..]);
+
+        let mut cleared = a.clone();
+        cleared.clear_set_bits_of(&b);
+        assert_eq!(cleared, a.and_not(&b));
+        for i in 0..1000 {
+            if !b.is_set(i) {
+                assert_eq!(cleared.get(i), a.get(i));
+            }
+        }
+        assert_eq!(a, Bitmap::from_bools(&generate(1000)));
+    }
+
+    #[test]
+    fn test_chunks() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for chunk_bits in [1, 7, 8, 64, 100, 1000, 2000] {
+            let mut joined = Vec::new();
+            for chunk in bitmap.chunks(chunk_bits) {
+                assert!(chunk.num_bits() <= chunk_bits);
+                joined.extend((0..chunk.num_bits()).map(|i| chunk.get(i).unwrap()));
+            }
+            assert_eq!(joined, bools);
+        }
+
+        let windows = bitmap.windows(10).collect::<Vec<_>>();
+        assert_eq!(windows.len(), 991);
+        for (start, window) in windows.iter().enumerate() {
+            assert_eq!(*window, Bitmap::from_bools(&bools[start..start + 10]));
+        }
+        assert_eq!(bitmap.windows(1001).count(), 0);
+    }
+
+    #[test]
+    fn test_extract() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for (start, len) in [
+            (0, 0),
+            (0, 70),
+            (0, 1000),
+            (3, 61),
+            (64, 128),
+            (999, 1),
+            (1000, 0),
+        ] {
+            let extracted = bitmap.extract(start, len);
+            assert_eq!(extracted, bitmap.slice(start, len));
+            assert_eq!(extracted.capacity_bytes(), extracted.num_bytes());
+            assert_eq!(extracted.validate(), Ok(()));
+        }
+
+        let shared = bitmap.slice(0, 70);
+        assert!(Arc::ptr_eq(&shared.buf, &bitmap.buf));
+        assert!(!Arc::ptr_eq(&bitmap.extract(0, 70).buf, &bitmap.buf));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for len in [0, 1, 70, 128, 999] {
+            let shared = bitmap.slice(0, len);
+            let sliced = Bitmap::from_bools(&[&[true], &bools[..len]].concat()).slice(1, len);
+            let mut padded = Bitmap::from_bools(&bools[..len]);
+            padded.align_to(64);
+            assert_eq!(shared, sliced);
+            assert_eq!(shared, padded);
+
+            let normalized = [shared, sliced, padded].map(|b| b.normalize());
+            for n in &normalized {
+                assert_eq!(n.capacity_bytes(), n.num_bytes());
+                assert_eq!(n.validate(), Ok(()));
+                assert_eq!(n.buf().as_slice(), normalized[0].buf().as_slice());
+            }
+        }
+
+        let canonical = Bitmap::from_bools(&bools[..70]);
+        assert!(Arc::ptr_eq(&canonical.normalize().buf, &canonical.buf));
+    }
+
+    #[test]
+    fn test_split_every() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+
+        for n in [1, 7, 8, 64, 72, 100, 1000, 2000] {
+            let segments = bitmap.split_every(n);
+            assert_eq!(segments.len(), 1000usize.div_ceil(n));
+            assert!(segments.iter().all(|s| s.num_bits() <= n));
+            assert_eq!(Bitmap::concat_many(&segments), bitmap);
+        }
+
+        assert!(Bitmap::new(0).split_every(8).is_empty());
+        let segments = Bitmap::ones(256).slice(0, 70).split_every(16);
+        assert_eq!(segments.last(), Some(&Bitmap::ones(6)));
+        assert_eq!(segments.last().unwrap().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_buffer_reuse() {
+        let bitmap = Bitmap::from_bools(&[true; 100]);
+        let shared = bitmap.clone();
+        assert!(shared.into_buffer().is_none());
+
+        let buf = bitmap.into_buffer().unwrap();
+        let ptr = buf.as_ptr();
+
+        let bitmap = Bitmap::from_buf_cleared(buf, 90);
+        assert_eq!(bitmap.num_bits(), 90);
+        assert_eq!(bitmap.count_ones(), 0);
+        assert_eq!(bitmap.buf().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_is_set() {
+        let bitmap = Bitmap::from_bools(&[true, false, true]);
+        assert!(bitmap.is_set(0));
+        assert!(!bitmap.is_set(1));
+        assert!(bitmap.is_set(2));
+        assert!(!bitmap.is_set(3));
+        assert!(!bitmap.is_set(usize::MAX));
+    }
+
+    #[test]
+    fn test_set_where() {
+        let bitmap = Bitmap::from_bools(&generate(300));
+        let mut mask_bools = generate(300);
+        mask_bools.rotate_left(17);
+        let mask = Bitmap::from_bools(&mask_bools);
+
+        let mut set = bitmap.clone();
+        set.set_where(&mask, true);
+        assert_eq!(set, &bitmap | &mask);
+
+        let mut cleared = bitmap.clone();
+        cleared.set_where(&mask, false);
+        assert_eq!(cleared, &bitmap & &!&mask);
+        assert_eq!(cleared, bitmap.and_not(&mask));
+    }
+
+    #[test]
+    fn test_prefix_popcount() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let prefix = bitmap.prefix_popcount();
+
+        for (start, end) in [
+            (0, 0),
+            (0, 1000),
+            (3, 3),
+            (3, 64),
+            (64, 128),
+            (65, 127),
+            (100, 999),
+            (999, 1000),
+        ] {
+            assert_eq!(
+                prefix.range_count(start, end),
+                bitmap.count_ones_in_range(start, end - start)
+            );
+        }
+
+        let counts = bitmap.prefix_counts();
+        assert_eq!(counts.len(), 17);
+        assert_eq!(*counts.last().unwrap(), bitmap.count_ones());
+    }
+
+    #[test]
+    fn test_count_ones_in_ranges() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let ranges = [
+            (0, 0),
+            (0, 1000),
+            (3, 61),
+            (64, 128),
+            (65, 127),
+            (100, 899),
+            (999, 1),
+        ];
+
+        let counts = bitmap.count_ones_in_ranges(&ranges);
+        assert_eq!(counts.len(), ranges.len());
+        for (&(start, len), count) in ranges.iter().zip(counts) {
+            assert_eq!(count, bitmap.count_ones_in_range(start, len));
+        }
+
+        assert!(bitmap.count_ones_in_ranges(&[]).is_empty());
+        assert_eq!(
+            Bitmap::ones(256)
+                .slice(0, 70)
+                .count_ones_in_ranges(&[(60, 10)]),
+            vec![10]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_count_ones_in_ranges_out_of_bounds() {
+        Bitmap::new(64).count_ones_in_ranges(&[(60, 5)]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_huge_new() {
+        Bitmap::new(usize::MAX);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_huge_from_buf() {
+        Bitmap::from_buf(Arc::new(Buffer::new(8)), usize::MAX);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_huge_slice() {
+        Bitmap::new(64).slice(usize::MAX, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_huge_count_ones_in_range() {
+        Bitmap::new(64).count_ones_in_range(usize::MAX, 1);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        for len in [0, 1, 63, 64, 65, 1000] {
+            let f = |i: usize| i.is_multiple_of(2) || i % 7 == 3;
+            let bools = (0..len).map(f).collect::<Vec<_>>();
+            assert_eq!(Bitmap::from_fn(len, f), Bitmap::from_bools(&bools));
+        }
+    }
+
+    #[test]
+    fn test_get_set_bits_u64() {
+        let bools = generate(300);
+        let mut bitmap = Bitmap::from_bools(&bools);
+
+        for (start, len) in [
+            (0, 0),
+            (0, 64),
+            (3, 61),
+            (60, 10),
+            (100, 64),
+            (236, 64),
+            (299, 1),
+        ] {
+            let expected = (0..len).fold(0u64, |acc, i| acc | (u64::from(bools[start + i]) << i));
+            assert_eq!(bitmap.get_bits_u64(start, len), expected);
+        }
+
+        bitmap.set_bits_u64(60, 10, 0b1010101010);
+        assert_eq!(bitmap.get_bits_u64(60, 10), 0b1010101010);
+        assert_eq!(bitmap.get(59).unwrap(), bools[59]);
+        assert_eq!(bitmap.get(70).unwrap(), bools[70]);
+    }
+
+    #[test]
+    fn test_swap() {
+        let bools = generate(500);
+        let original = Bitmap::from_bools(&bools);
+        let mut bitmap = original.clone();
+
+        let (a, b) = (
+            bools.iter().position(|&b| b).unwrap(),
+            bools.iter().position(|&b| !b).unwrap(),
+        );
+        bitmap.swap_bits(a, b);
+        assert!(!bitmap.get(a).unwrap());
+        assert!(bitmap.get(b).unwrap());
+        bitmap.swap_bits(a, b);
+        assert_eq!(bitmap, original);
+
+        bitmap.swap_ranges(3, 200, 150);
+        for i in 0..150 {
+            assert_eq!(bitmap.get(3 + i).unwrap(), bools[200 + i]);
+            assert_eq!(bitmap.get(200 + i).unwrap(), bools[3 + i]);
+        }
+        assert_eq!(bitmap.get(153).unwrap(), bools[153]);
+        assert_eq!(bitmap.get(350).unwrap(), bools[350]);
+        bitmap.swap_ranges(200, 3, 150);
+        assert_eq!(bitmap, original);
+    }
+
+    #[test]
+    fn test_align_to() {
+        let bools = generate(100);
+        let mut bitmap = Bitmap::from_bools(&bools);
+        assert_eq!(bitmap.capacity_bytes(), 13);
+        assert!(!bitmap.is_aligned_to(32));
+
+        bitmap.align_to(32);
+        assert_eq!(bitmap.capacity_bytes(), 32);
+        assert!(bitmap.is_aligned_to(32));
+        assert_eq!(bitmap.num_bits(), 100);
+        assert_eq!(bitmap, Bitmap::from_bools(&bools));
+
+        bitmap.align_to(64);
+        assert_eq!(bitmap.capacity_bytes(), 64);
+        assert!(bitmap.buf().as_slice()[13..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_simd_align_prefix() {
+        for len in [0, 1, 100, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            for boundary in [1, 8, 32, 64, 4096] {
+                let prefix = bitmap.simd_align_prefix(boundary);
+                let (head, body) = bitmap.as_byte_slice().split_at(prefix);
+                assert_eq!(head.len() + body.len(), bitmap.num_bytes());
+                if !body.is_empty() {
+                    assert!((body.as_ptr() as usize).is_multiple_of(boundary));
+                }
+            }
+        }
+
+        assert_eq!(Bitmap::from_bools(&generate(100)).simd_align_prefix(32), 0);
+    }
+
+    #[test]
+    fn test_slice_accessors() {
+        let mut bitmap = Bitmap::new(100);
+        assert_eq!(bitmap.as_word_slice().len(), 2);
+        assert_eq!(bitmap.as_byte_slice().len(), 13);
+        assert!(Bitmap::new(0).as_word_slice().is_empty());
+
+        for word in bitmap.as_mut_word_slice() {
+            *word = u64::MAX.to_le();
+        }
+        bitmap.clear_padding_bits();
+        assert_eq!(bitmap.count_ones(), 100);
+        assert_eq!(u64::from_le(bitmap.as_word_slice()[1]), (1 << 36) - 1);
+
+        bitmap.as_mut_byte_slice()[0] = 0;
+        assert_eq!(bitmap.count_ones(), 92);
+        assert_eq!(bitmap.as_byte_slice()[12], 0b1111);
+
+        let shared = bitmap.slice(0, 10);
+        let mut cleared = shared.clone();
+        cleared.clear_padding_bits();
+        assert_eq!(cleared, shared);
+        assert!(
+            cleared.as_byte_slice().len() == 2
+                && cleared.buf().as_slice()[2..].iter().all(|&b| b == 0)
+        );
+        assert_eq!(bitmap.count_ones(), 92);
+    }
+
+    #[test]
+    fn test_validate() {
+        let bitmap = Bitmap::from_bools(&generate(100));
+        assert_eq!(bitmap.validate(), Ok(()));
+        assert_eq!(Bitmap::new(0).validate(), Ok(()));
+
+        let small = unsafe { Bitmap::from_buf_unchecked(Arc::new(Buffer::new(12)), 100) };
+        assert_eq!(
+            small.validate(),
+            Err(ValidationError::BufferTooSmall {
+                num_bits: 100,
+                buffer_len: 12
+            })
+        );
+
+        let mut buf = Buffer::new(16);
+        buf.as_mut_slice()[12] = 0b11110000;
+        buf.as_mut_slice()[15] = 1;
+        let dirty = Bitmap::from_buf(Arc::new(buf), 100);
+        assert_eq!(
+            dirty.validate(),
+            Err(ValidationError::DirtyPadding {
+                num_padding_bits: 5
+            })
+        );
+
+        let mut cleaned = dirty.clone();
+        cleaned.clear_padding_bits();
+        assert_eq!(cleaned.validate(), Ok(()));
+
+        assert!(bitmap.slice(0, 50).validate().is_err());
+    }
+
+    #[test]
+    fn test_with_padding_cleared() {
+        let shared = Bitmap::from_bools(&generate(200));
+
+        let dirty = shared.slice(0, 70);
+        let cleaned = dirty.with_padding_cleared();
+        assert_eq!(cleaned, dirty);
+        assert_eq!(cleaned.validate(), Ok(()));
+        assert!(!Arc::ptr_eq(&cleaned.buf, &dirty.buf));
+        assert_eq!(shared, Bitmap::from_bools(&generate(200)));
+
+        let clean = shared.with_padding_cleared();
+        assert!(Arc::ptr_eq(&clean.buf, &shared.buf));
+        let empty = Bitmap::new(0);
+        assert!(Arc::ptr_eq(&empty.with_padding_cleared().buf, &empty.buf));
+    }
+
+    #[test]
+    fn test_iter_runs() {
+        for len in [0, 1, 64, 65, 1000] {
+            let bools = generate(len);
+            let bitmap = Bitmap::from_bools(&bools);
+
+            let mut pos = 0;
+            let mut prev = None;
+            for (value, start, len) in bitmap.iter_runs() {
+                assert_eq!(start, pos);
+                assert!(len > 0);
+                assert_ne!(prev, Some(value));
+                assert!(bools[start..start + len].iter().all(|&b| b == value));
+                pos += len;
+                prev = Some(value);
+            }
+            assert_eq!(pos, len);
+        }
+
+        let runs = Bitmap::from_bools(&[true; 70])
+            .iter_runs()
+            .collect::<Vec<_>>();
+        assert_eq!(runs, vec![(true, 0, 70)]);
+        let runs = Bitmap::new(70).iter_runs().collect::<Vec<_>>();
+        assert_eq!(runs, vec![(false, 0, 70)]);
+    }
+
+    #[test]
+    fn test_to_run_pairs() {
+        for len in [0, 1, 64, 65, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let pairs = bitmap.to_run_pairs();
+
+            let mut pos = 0;
+            for (i, &(value, start, run_len)) in pairs.iter().enumerate() {
+                assert_eq!(start, pos);
+                assert!(run_len > 0);
+                if i > 0 {
+                    assert_ne!(pairs[i - 1].0, value);
+                }
+                pos += run_len;
+            }
+            assert_eq!(pos, len);
+        }
+
+        // dirty padding doesn't show up as a run
+        let pairs = Bitmap::ones(256).slice(0, 70).to_run_pairs();
+        assert_eq!(pairs, vec![(true, 0, 70)]);
+        assert!(Bitmap::new(0).to_run_pairs().is_empty());
+    }
+
+    #[test]
+    fn test_from_word_fn() {
+        for len in [0, 1, 63, 64, 65, 1000] {
+            let bitmap = Bitmap::from_word_fn(len, |_| !0u64);
+            assert_eq!(bitmap, Bitmap::ones(len));
+            assert_eq!(bitmap, Bitmap::from_bools(&vec![true; len]));
+            assert_eq!(bitmap.validate(), Ok(()));
+        }
+
+        let striped = Bitmap::from_word_fn(130, |i| if i % 2 == 0 { 0xFF } else { 0 });
+        assert_eq!(striped.count_ones(), 10);
+        assert!(striped.get(135).is_none());
+        assert!(striped.get(128).unwrap());
+    }
+
+    #[test]
+    fn test_count_transitions() {
+        for len in [0, 1, 2, 63, 64, 65, 128, 1000] {
+            let bools = generate(len);
+            let expected = bools.windows(2).filter(|w| w[0] != w[1]).count();
+            let bitmap = Bitmap::from_bools(&bools);
+            assert_eq!(bitmap.count_transitions(), expected);
+            assert_eq!(
+                bitmap.count_transitions(),
+                bitmap.iter_runs().count().saturating_sub(1)
+            );
+        }
+
+        assert_eq!(Bitmap::ones(130).count_transitions(), 0);
+        assert_eq!(Bitmap::ones(130).slice(0, 65).count_transitions(), 0);
+    }
+
+    #[test]
+    fn test_clone_into() {
+        let src = Bitmap::from_bools(&generate(100));
+        let mut dst = Bitmap::ones(200);
+        let ptr = dst.buf().as_ptr();
+
+        src.clone_into(&mut dst);
+        assert_eq!(dst, src);
+        assert_eq!(dst.buf().as_ptr(), ptr);
+        assert_eq!(dst.validate(), Ok(()));
+
+        let mut small = Bitmap::new(10);
+        src.clone_into(&mut small);
+        assert_eq!(small, src);
+        assert_eq!(small.buf().as_ptr(), src.buf().as_ptr());
+
+        let shared = dst.clone();
+        Bitmap::ones(50).clone_into(&mut dst);
+        assert_eq!(dst, Bitmap::ones(50));
+        assert_eq!(shared, src);
+    }
+
+    #[test]
+    fn test_concat_many() {
+        let bools = generate(2000);
+        let mut parts = Vec::new();
+        let mut offset = 0;
+        for len in [0, 1, 7, 64, 3, 100, 65, 0, 500, 9] {
+            parts.push(Bitmap::from_bools(&bools[offset..offset + len]));
+            offset += len;
+        }
+
+        let folded = parts
+            .iter()
+            .fold(Bitmap::new(0), |acc, part| acc.concat(part));
+        let joined = Bitmap::concat_many(&parts);
+        assert_eq!(joined, folded);
+        assert_eq!(joined, Bitmap::from_bools(&bools[..offset]));
+        assert_eq!(Bitmap::concat_many(&[]), Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_checked_concat() {
+        let a = Bitmap::from_bools(&generate(100));
+        let b = Bitmap::from_bools(&generate(77));
+        assert_eq!(a.checked_concat(&b), Some(a.concat(&b)));
+        assert_eq!(
+            a.checked_concat(&b).unwrap(),
+            Bitmap::from_bools(&[generate(100), generate(77)].concat())
+        );
+        assert_eq!(
+            Bitmap::new(0).checked_concat(&Bitmap::new(0)),
+            Some(Bitmap::new(0))
+        );
+
+        // Only the lengths are inspected before failing, so the buffers can be empty
+        let huge = |num_bits| Bitmap {
+            buf: Arc::new(Buffer::new(0)),
+            num_bits,
+        };
+        assert_eq!(
+            huge(usize::MAX / 2 + 1).checked_concat(&huge(usize::MAX / 2 + 1)),
+            None
+        );
+        assert_eq!(
+            huge(usize::MAX / 2).checked_concat(&huge(usize::MAX / 2 + 1)),
+            None
+        );
+        assert_eq!(huge(MAX_BITS).checked_concat(&huge(1)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bitmap too large")]
+    fn test_concat_overflow() {
+        let huge = Bitmap {
+            buf: Arc::new(Buffer::new(0)),
+            num_bits: usize::MAX / 2 + 1,
+        };
+        huge.concat(&huge);
+    }
+
+    #[test]
+    fn test_rotate() {
+        for len in [1, 13, 64, 100, 1000] {
+            let bools = generate(len);
+            let bitmap = Bitmap::from_bools(&bools);
+
+            for n in [0, 3, 13, 64, 77, len, 2 * len + 5] {
+                let left = bitmap.rotate_left(n);
+                let right = bitmap.rotate_right(n);
+                for (i, &b) in bools.iter().enumerate() {
+                    assert_eq!(left.get((i + n) % len).unwrap(), b);
+                    assert_eq!(right.get((i + len - n % len) % len).unwrap(), b);
+                }
+                assert_eq!(left.rotate_right(n), bitmap);
+            }
+        }
+
+        assert_eq!(Bitmap::new(0).rotate_left(5), Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_rotate_bytes_left() {
+        for len in [0, 8, 64, 120, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            for k in [0, 1, 3, 8, 15, len / 8, len] {
+                assert_eq!(bitmap.rotate_bytes_left(k), bitmap.rotate_left(k * 8));
+            }
+        }
+
+        let shared = Bitmap::from_bools(&generate(200)).slice(0, 96);
+        assert_eq!(shared.rotate_bytes_left(5), shared.rotate_left(40));
+        assert_eq!(shared.rotate_bytes_left(5).validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rotate_bytes_left_unaligned() {
+        Bitmap::new(13).rotate_bytes_left(1);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_sample() {
+        for density in [0.0, 0.1, 0.5, 0.9, 1.0] {
+            let bitmap = Bitmap::sample(100_000, density, 42);
+            let actual = bitmap.count_ones() as f64 / 100_000.0;
+            assert!((actual - density).abs() < 0.01, "{} vs {}", actual, density);
+            assert_eq!(bitmap, Bitmap::sample(100_000, density, 42));
+        }
+    }
+
+    #[test]
+    fn test_first_last_n_set() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+        let indices = (0..1000).filter(|&i| bools[i]).collect::<Vec<_>>();
+        assert_eq!(bitmap.to_indices(), indices);
+
+        for n in [0, 1, 10, 65, indices.len(), indices.len() + 10] {
+            let n_capped = n.min(indices.len());
+            assert_eq!(bitmap.first_n_set(n), indices[..n_capped]);
+            assert_eq!(bitmap.last_n_set(n), indices[indices.len() - n_capped..]);
+        }
+
+        assert!(Bitmap::new(100).last_n_set(3).is_empty());
+        assert_eq!(Bitmap::ones(130).slice(0, 70).last_n_set(2), vec![68, 69]);
+    }
+
+    #[test]
+    fn test_into() {
+        let a = Bitmap::from_bools(&generate(300));
+        let mut b_bools = generate(300);
+        b_bools.rotate_left(5);
+        let b = Bitmap::from_bools(&b_bools);
+        let (a_copy, b_copy) = (
+            Bitmap::from_bools(&generate(300)),
+            Bitmap::from_bools(&b_bools),
+        );
+
+        let mut dst = Bitmap::ones(500);
+        let ptr = dst.buf().as_ptr();
+
+        a.xor_into(&b, &mut dst);
+        assert_eq!(dst, &a ^ &b);
+        a.and_into(&b, &mut dst);
+        assert_eq!(dst, &a & &b);
+        a.or_into(&b, &mut dst);
+        assert_eq!(dst, &a | &b);
+        assert_eq!(dst.buf().as_ptr(), ptr);
+        assert_eq!(dst.validate(), Ok(()));
+
+        let mut small = Bitmap::new(3);
+        a.xor_into(&b, &mut small);
+        assert_eq!(small, &a ^ &b);
+
+        assert_eq!(a, a_copy);
+        assert_eq!(b, b_copy);
+    }
+
+    #[test]
+    fn test_set_range() {
+        let bools = generate(300);
+        let mut bitmap = Bitmap::from_bools(&bools);
+
+        bitmap.set_range(10, 200, true);
+        bitmap.set_range(50, 13, false);
+        bitmap.set_range(299, 0, false);
+        for (i, &b) in bools.iter().enumerate() {
+            let expected = match i {
+                50..63 => false,
+                10..210 => true,
+                _ => b,
+            };
+            assert_eq!(bitmap.get(i).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_set_ranges_bits() {
+        let ranges = [(200, 50), (10, 100), (60, 70), (299, 1), (5, 0), (64, 64)];
+        for len in [300, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+
+            let mut set = bitmap.clone();
+            set.set_ranges_bits(&ranges);
+            assert_eq!(set, &bitmap | &Bitmap::from_set_ranges(len, &ranges));
+            assert_eq!(bitmap, Bitmap::from_bools(&generate(len)));
+        }
+
+        let mut empty = Bitmap::new(0);
+        empty.set_ranges_bits(&[(0, 0)]);
+        assert_eq!(empty.num_bits(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_set_ranges_bits_out_of_bounds() {
+        Bitmap::new(100).set_ranges_bits(&[(0, 10), (90, 11)]);
+    }
+
+    #[test]
+    fn test_grow_by() {
+        let bools = generate(100);
+        let mut bitmap = Bitmap::from_bools(&bools);
+        let ones = bitmap.count_ones();
+
+        bitmap.grow_by(77, true);
+        assert_eq!(bitmap.num_bits(), 177);
+        assert_eq!(bitmap.count_ones(), ones + 77);
+        assert_eq!(bitmap.slice(0, 100), Bitmap::from_bools(&bools));
+        assert!((100..177).all(|i| bitmap.get(i).unwrap()));
+
+        bitmap.grow_by(3, false);
+        assert_eq!(bitmap.count_ones(), ones + 77);
+        assert_eq!(bitmap.num_bits(), 180);
+
+        // growing into the dirty tail of a shared buffer
+        let mut shared = Bitmap::ones(200).slice(0, 10);
+        shared.grow_by(50, false);
+        assert_eq!(shared.count_ones(), 10);
+        assert_eq!(shared.validate(), Ok(()));
+
+        shared.resize(5, false);
+        assert_eq!(shared.count_ones(), 5);
+        assert_eq!(shared.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_trim_zeros() {
+        let mut bools = vec![false; 70];
+        bools.extend(generate(100));
+        bools[70] = true;
+        bools[169] = true;
+        bools.extend([false; 13]);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        assert_eq!(bitmap.find_first_set(), Some(70));
+        assert_eq!(bitmap.find_last_set(), Some(169));
+
+        let trailing = bitmap.trim_trailing_zeros();
+        assert_eq!(trailing, Bitmap::from_bools(&bools[..170]));
+
+        let (offset, leading) = bitmap.trim_leading_zeros();
+        assert_eq!(offset, 70);
+        assert_eq!(leading, Bitmap::from_bools(&bools[70..]));
+
+        let zeros = Bitmap::new(100);
+        assert_eq!(zeros.find_first_set(), None);
+        assert_eq!(zeros.find_last_set(), None);
+        assert_eq!(zeros.trim_trailing_zeros().num_bits(), 0);
+        assert_eq!(zeros.trim_leading_zeros(), (100, Bitmap::new(0)));
+        assert_eq!(Bitmap::ones(200).slice(0, 10).find_last_set(), Some(9));
+    }
+
+    #[test]
+    fn test_eq_ignoring_length() {
+        let bools = generate(100);
+        let short = Bitmap::from_bools(&bools);
+
+        let mut long = Bitmap::from_bools(&bools);
+        long.grow_by(90, false);
+        assert!(short.eq_ignoring_length(&long));
+        assert!(long.eq_ignoring_length(&short));
+        assert_ne!(short, long);
+
+        long.set(150);
+        assert!(!short.eq_ignoring_length(&long));
+        assert!(!long.eq_ignoring_length(&short));
+
+        long.clear(150);
+        long.set(101);
+        assert!(!short.eq_ignoring_length(&long));
+
+        // dirty padding of a shared slice doesn't matter
+        let ones = Bitmap::ones(100);
+        assert!(ones.slice(0, 10).eq_ignoring_length(&Bitmap::ones(10)));
+        assert!(!ones.slice(0, 10).eq_ignoring_length(&ones));
+        assert!(Bitmap::new(0).eq_ignoring_length(&Bitmap::new(10)));
+    }
+
+    #[test]
+    fn test_find_pattern() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for (start, len) in [(0, 100), (0, 1000), (437, 70), (900, 100), (3, 64)] {
+            let pattern = Bitmap::from_bools(&bools[start..start + len]);
+            let found = bitmap.find_pattern(&pattern).unwrap();
+            assert!(found <= start);
+            assert!(bitmap.range_eq(found, &pattern, 0, len));
+        }
+
+        let mut bitmap = Bitmap::new(300);
+        bitmap.set_range(150, 3, true);
+        bitmap.set(154);
+        let pattern = Bitmap::from_bools(&[true, true, true, false, true]);
+        assert_eq!(bitmap.find_pattern(&pattern), Some(150));
+        assert_eq!(bitmap.find_pattern(&Bitmap::new(10)), Some(0));
+        assert_eq!(bitmap.find_pattern(&Bitmap::ones(4)), None);
+        assert_eq!(bitmap.find_pattern(&Bitmap::new(301)), None);
+        assert_eq!(bitmap.find_pattern(&Bitmap::new(0)), Some(0));
+    }
+
+    #[test]
+    fn test_range_eq() {
+        let bools = generate(1000);
+        let a = Bitmap::from_bools(&bools);
+
+        for (a_start, b_start, len) in [(0, 0, 1000), (0, 64, 500), (3, 70, 900), (61, 5, 130)] {
+            let b = Bitmap::from_bools(&[&generate(b_start)[..], &bools[a_start..]].concat());
+            assert!(a.range_eq(a_start, &b, b_start, len));
+            assert!(b.range_eq(b_start, &a, a_start, len));
+
+            let mut flipped = b.clone();
+            let mid = b_start + len / 2;
+            if flipped.is_set(mid) {
+                flipped.clear(mid);
+            } else {
+                flipped.set(mid);
+            }
+            assert!(!a.range_eq(a_start, &flipped, b_start, len));
+            assert!(a.range_eq(a_start, &flipped, b_start, len / 2));
+        }
+
+        assert!(a.range_eq(5, &Bitmap::new(0), 0, 0));
+        let ones = Bitmap::ones(256);
+        assert!(ones.slice(0, 70).range_eq(60, &ones, 3, 10));
+    }
+
+    #[test]
+    fn test_eq() {
+        for len in [0, 1, 63, 64, 65, 255, 256, 257, 1000] {
+            let bools = generate(len);
+            let a = Bitmap::from_bools(&bools);
+            assert_eq!(a, Bitmap::from_bools(&bools));
+
+            for i in [0, len / 2, len.saturating_sub(1)]
+                .into_iter()
+                .filter(|_| len > 0)
+            {
+                let mut b = a.clone();
+                if bools[i] {
+                    b.clear(i);
+                } else {
+                    b.set(i);
+                }
+                assert_ne!(a, b);
+            }
+
+            // dirty padding is ignored
+            let mut padded = bools.clone();
+            padded.extend([true; 9]);
+            assert_eq!(Bitmap::from_bools(&padded).slice(0, len), a);
+        }
+        assert_ne!(Bitmap::new(10), Bitmap::new(11));
+    }
+
+    #[test]
+    fn test_and_count_in_range() {
+        let a = Bitmap::from_bools(&generate(500));
+        let b = a.rotate_left(3);
+
+        for (start, len) in [(0, 0), (0, 500), (3, 61), (64, 64), (70, 300), (499, 1)] {
+            let mut window = Bitmap::new(500);
+            window.set_range(start, len, true);
+            let expected = (&(&a & &window) & &(&b & &window)).count_ones();
+            assert_eq!(a.and_count_in_range(&b, start, len), expected);
+        }
+    }
+
+    #[test]
+    fn test_and_not_count_in_range() {
+        let a = Bitmap::from_bools(&generate(500));
+        let b = a.rotate_left(3);
+
+        for (start, len) in [(0, 0), (0, 500), (3, 61), (64, 64), (70, 300), (499, 1)] {
+            let mut window = Bitmap::new(500);
+            window.set_range(start, len, true);
+            let expected = (&a & &window).and_not(&(&b & &window)).count_ones();
+            assert_eq!(a.and_not_count_in_range(&b, start, len), expected);
+        }
+
+        // the bitmaps only have to cover the range
+        let long = Bitmap::ones(100);
+        assert_eq!(long.and_not_count_in_range(&Bitmap::new(70), 60, 10), 10);
+        assert_eq!(long.and_not_count_in_range(&Bitmap::ones(70), 60, 10), 0);
+    }
+
+    #[test]
+    fn test_iter_set_bits_in_range() {
+        let bitmap = Bitmap::from_bools(&generate(500));
+
+        for (start, len) in [(0, 0), (0, 500), (3, 61), (64, 64), (70, 300), (499, 1)] {
+            let expected = bitmap
+                .iter_set_bits()
+                .filter(|i| (start..start + len).contains(i))
+                .collect::<Vec<_>>();
+            let actual = bitmap
+                .iter_set_bits_in_range(start, len)
+                .collect::<Vec<_>>();
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn test_set_ranges() {
+        for len in [0, 1, 63, 64, 65, 128, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let expected = bitmap
+                .iter_runs()
+                .filter(|run| run.0)
+                .map(|(_, start, len)| (start, len))
+                .collect::<Vec<_>>();
+            assert_eq!(bitmap.set_ranges(), expected);
+        }
+
+        assert_eq!(Bitmap::ones(128).set_ranges(), vec![(0, 128)]);
+        assert_eq!(Bitmap::ones(200).slice(0, 70).set_ranges(), vec![(0, 70)]);
+        assert!(Bitmap::new(100).set_ranges().is_empty());
+    }
+
+    #[test]
+    fn test_run_starts() {
+        for len in [0, 1, 63, 64, 65, 128, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let starts = bitmap.run_starts();
+            assert_eq!(starts.count_ones(), bitmap.set_ranges().len());
+            assert_eq!(
+                starts.to_indices(),
+                bitmap.set_ranges().iter().map(|r| r.0).collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(Bitmap::ones(130).run_starts().to_indices(), vec![0]);
+    }
+
+    #[test]
+    fn test_slice_allocation_boundary() {
+        // 512 bits fill the 64 byte allocation exactly, so reading a word past the sliced range
+        //  would read outside of the allocation.
+        let bools = generate(512);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for (start, len) in [
+            (1, 511),
+            (63, 449),
+            (65, 447),
+            (448, 64),
+            (449, 63),
+            (511, 1),
+        ] {
+            let sliced = bitmap.slice(start, len);
+            assert_eq!(sliced, Bitmap::from_bools(&bools[start..start + len]));
+            assert_eq!(sliced.validate(), Ok(()));
+        }
+
+        let mut sliced = Bitmap::from_bools(&bools[..504]).slice(8, 496);
+        assert_eq!(sliced.capacity_bytes(), 62);
+        sliced.set(495);
+        assert_eq!(
+            sliced.count_ones(),
+            bools[8..504].iter().filter(|&&b| b).count() + usize::from(!bools[503])
+        );
+    }
+
+    #[test]
+    fn test_dilate_erode() {
+        let mut bitmap = Bitmap::new(300);
+        for (start, len) in [(0, 10), (30, 1), (50, 40), (120, 100), (295, 5)] {
+            bitmap.set_range(start, len, true);
+        }
+
+        let dilated = bitmap.dilate(3);
+        assert_eq!(
+            dilated.set_ranges(),
+            vec![(0, 13), (27, 7), (47, 46), (117, 106), (292, 8)]
+        );
+        assert_eq!(dilated.erode(3), bitmap);
+
+        let eroded = bitmap.erode(2);
+        assert_eq!(
+            eroded.set_ranges(),
+            vec![(0, 8), (52, 36), (122, 96), (297, 3)]
+        );
+        assert_eq!(bitmap.dilate(0), bitmap);
+        assert_eq!(bitmap.erode(0), bitmap);
+
+        // merged runs stay merged
+        assert_eq!(
+            bitmap.dilate(10).set_ranges(),
+            vec![(0, 100), (110, 120), (285, 15)]
+        );
+        assert_eq!(Bitmap::new(10).dilate(usize::MAX), Bitmap::new(10));
+        assert_eq!(Bitmap::ones(10).dilate(usize::MAX), Bitmap::ones(10));
+    }
+
+    #[test]
+    fn test_or_shifted() {
+        let a_bools = generate(300);
+        let mut b_bools = generate(250);
+        b_bools.reverse();
+        let a = Bitmap::from_bools(&a_bools);
+        let b = Bitmap::from_bools(&b_bools);
+
+        for shift in [
+            0isize,
+            1,
+            5,
+            63,
+            64,
+            70,
+            299,
+            300,
+            1000,
+            -1,
+            -5,
+            -63,
+            -64,
+            -70,
+            -249,
+            -250,
+            isize::MIN,
+        ] {
+            let result = a.or_shifted(&b, shift);
+            for (i, &a_bit) in a_bools.iter().enumerate() {
+                let src = i as i128 - shift as i128;
+                let b_bit = src >= 0 && (src as usize) < b_bools.len() && b_bools[src as usize];
+                assert_eq!(
+                    result.get(i).unwrap(),
+                    a_bit || b_bit,
+                    "shift {} at {}",
+                    shift,
+                    i
+                );
+            }
+            assert_eq!(result.validate(), Ok(()));
+        }
+
+        // Dirty padding of a longer `other` isn't shifted in
+        let long = Bitmap::ones(1000).slice(0, 700);
+        let result = Bitmap::new(300).or_shifted(&long, -500);
+        assert_eq!(result, Bitmap::from_set_ranges(300, &[(0, 200)]));
+        let result = Bitmap::new(300).or_shifted(&long.slice(0, 3), 97);
+        assert_eq!(result, Bitmap::from_set_ranges(300, &[(97, 3)]));
+    }
+
+    #[test]
+    fn test_checked_get() {
+        let bitmap = Bitmap::from_bools(&[true, false]);
+        assert_eq!(bitmap.checked_get(0), Ok(true));
+        assert_eq!(bitmap.checked_get(1), Ok(false));
+
+        let err = bitmap.checked_get(2).unwrap_err();
+        assert_eq!(
+            err,
+            OutOfBounds {
+                index: 2,
+                num_bits: 2
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "index 2 is out of bounds for bitmap of 2 bits"
+        );
+    }
+
+    #[test]
+    fn test_map_words() {
+        for len in [0, 1, 63, 64, 65, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let mut mapped = bitmap.clone();
+            mapped.map_words(|w| !w);
+            assert_eq!(mapped, !&bitmap);
+            assert_eq!(mapped.validate(), Ok(()));
+            assert_eq!(mapped.count_ones(), len - bitmap.count_ones());
+        }
+
+        let mut seen = Vec::new();
+        let mut bitmap = Bitmap::ones(200).slice(0, 70);
+        bitmap.map_words(|w| {
+            seen.push(w);
+            w.rotate_left(1)
+        });
+        assert_eq!(seen, vec![u64::MAX, 0b111111]);
+        assert_eq!(
+            bitmap.iter_words().collect::<Vec<_>>(),
+            vec![u64::MAX, 0b111110]
+        );
+    }
+
+    #[test]
+    fn test_partition_point() {
+        let bools = generate(1000);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for threshold in [0, 1, 63, 64, 65, 500, 999, 1000, 5000] {
+            let expected = bools.iter().take(threshold).filter(|&&b| b).count();
+            assert_eq!(bitmap.rank(threshold.min(1000)), expected);
+            assert_eq!(bitmap.partition_point(|i| i < threshold), expected);
+        }
+        assert_eq!(Bitmap::new(0).partition_point(|_| true), 0);
+    }
+
+    #[test]
+    fn test_iter_gaps() {
+        let mut bitmap = Bitmap::new(300);
+        for i in [5, 6, 70, 200, 290] {
+            bitmap.set(i);
+        }
+
+        assert_eq!(
+            bitmap.iter_gaps().collect::<Vec<_>>(),
+            vec![(5, 6, 0), (6, 70, 63), (70, 200, 129), (200, 290, 89)]
+        );
+        assert_eq!(bitmap.trailing_zeros(), 5);
+        assert_eq!(bitmap.leading_zeros(), 9);
+
+        assert_eq!(Bitmap::new(64).iter_gaps().count(), 0);
+        assert_eq!(Bitmap::new(64).trailing_zeros(), 64);
+        assert_eq!(Bitmap::new(64).leading_zeros(), 64);
+        let mut single = Bitmap::new(64);
+        single.set(10);
+        assert_eq!(single.iter_gaps().count(), 0);
+    }
+
+    #[test]
+    fn test_overlap_ranges() {
+        for len in [0, 1, 64, 65, 1000] {
+            let a = Bitmap::from_bools(&generate(len));
+            let b = a.rotate_left(1);
+            assert_eq!(a.overlap_ranges(&b), (&a & &b).set_ranges());
+        }
+
+        let a = Bitmap::ones(200).slice(0, 100);
+        let mut b = Bitmap::new(100);
+        b.set_range(90, 10, true);
+        assert_eq!(a.overlap_ranges(&b), vec![(90, 10)]);
+    }
+
+    #[test]
+    fn test_popcount_per_block() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+
+        for block_bits in [1, 7, 64, 100, 1000, 5000] {
+            let counts = bitmap.popcount_per_block(block_bits);
+            assert_eq!(counts.len(), 1000usize.div_ceil(block_bits));
+            for (i, &count) in counts.iter().enumerate() {
+                let start = i * block_bits;
+                let len = block_bits.min(1000 - start);
+                assert_eq!(count, bitmap.count_ones_in_range(start, len));
+            }
+        }
+        assert!(Bitmap::new(0).popcount_per_block(8).is_empty());
+    }
+
+    #[test]
+    fn test_downsample() {
+        let bools = generate(1001);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        let or = bitmap.downsample_or(4);
+        let and = bitmap.downsample_and(4);
+        assert_eq!(or.num_bits(), 251);
+        assert_eq!(and.num_bits(), 251);
+        for (k, group) in bools.chunks(4).enumerate() {
+            assert_eq!(or.get(k), Some(group.iter().any(|&b| b)));
+            assert_eq!(and.get(k), Some(group.iter().all(|&b| b)));
+        }
+
+        assert_eq!(bitmap.downsample_or(1), bitmap);
+        assert_eq!(bitmap.downsample_and(1), bitmap);
+        assert_eq!(Bitmap::ones(10).downsample_and(4), Bitmap::ones(3));
+        assert_eq!(Bitmap::new(0).downsample_or(4), Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_upsample() {
+        let bools = generate(300);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for factor in [0, 1, 3, 64, 100] {
+            let up = bitmap.upsample(factor);
+            assert_eq!(up.num_bits(), 300 * factor);
+            for i in 0..up.num_bits() {
+                assert_eq!(up.get(i), Some(bools[i / factor]));
+            }
+            if factor > 0 {
+                assert_eq!(up.downsample_and(factor), bitmap);
+            }
+        }
+
+        let coarse = bitmap.downsample_or(4).upsample(4);
+        assert_eq!(coarse.num_bits(), 300);
+        assert_eq!(&coarse & &bitmap, bitmap);
+    }
+
+    #[test]
+    fn test_block_popcounts() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+
+        for block_bits in [1, 8, 64, 128, 1024] {
+            let counts = bitmap.block_popcounts(block_bits);
+            assert_eq!(counts.len(), 1000usize.div_ceil(block_bits) + 1);
+            for (i, &count) in counts.iter().enumerate() {
+                let end = (i * block_bits).min(1000);
+                assert_eq!(count, bitmap.count_ones_in_range(0, end));
+            }
+        }
+        assert_eq!(bitmap.block_popcounts(64), bitmap.prefix_counts());
+        assert_eq!(Bitmap::new(0).block_popcounts(8), vec![0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_block_popcounts_not_power_of_two() {
+        Bitmap::new(100).block_popcounts(24);
+    }
+
+    #[test]
+    fn test_diff_iter() {
+        for (a_len, b_len) in [
+            (0, 0),
+            (1, 1),
+            (64, 64),
+            (65, 65),
+            (1000, 1000),
+            (1000, 130),
+        ] {
+            let a_bools = generate(a_len);
+            let b_bools = generate(b_len + 3)[3..].to_vec();
+            let a = Bitmap::from_bools(&a_bools);
+            let b = Bitmap::from_bools(&b_bools);
+
+            let expected = (0..a_len.min(b_len))
+                .filter(|&i| a_bools[i] != b_bools[i])
+                .map(|i| (i, b_bools[i]))
+                .collect::<Vec<_>>();
+            assert_eq!(a.diff_iter(&b).collect::<Vec<_>>(), expected);
+            assert_eq!(b.diff_iter(&a).count(), expected.len());
+        }
+
+        let mut b = Bitmap::new(100);
+        b.set(70);
+        assert_eq!(
+            Bitmap::ones(256).slice(0, 71).diff_iter(&b).last(),
+            Some((69, false))
+        );
+        assert_eq!(
+            Bitmap::ones(256)
+                .slice(0, 70)
+                .diff_iter(&Bitmap::ones(100))
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        for len in [0, 1, 64, 65, 1000] {
+            let a = Bitmap::from_bools(&generate(len));
+            let b = a.rotate_left(3);
+            assert_eq!(a.changed_ranges(&b), (&a ^ &b).set_ranges());
+        }
+
+        let a = Bitmap::from_bools(&generate(300));
+        let mut b = a.clone();
+        assert!(a.changed_ranges(&b).is_empty());
+        b.set_range(100, 50, true);
+        b.set_range(100, 10, false);
+        let mut expected = Bitmap::new(300);
+        for i in 100..150 {
+            if a.get(i) != b.get(i) {
+                expected.set(i);
+            }
+        }
+        assert_eq!(a.changed_ranges(&b), expected.set_ranges());
+    }
+
+    #[test]
+    fn test_saturating_slice() {
+        let bools = generate(100);
+        let bitmap = Bitmap::from_bools(&bools);
+
+        assert_eq!(bitmap.saturating_slice(10, 20), bitmap.slice(10, 20));
+        assert_eq!(
+            bitmap.saturating_slice(90, 20),
+            Bitmap::from_bools(&bools[90..])
+        );
+        assert_eq!(bitmap.saturating_slice(0, usize::MAX), bitmap);
+        assert_eq!(bitmap.saturating_slice(100, 5).num_bits(), 0);
+        assert_eq!(
+            bitmap.saturating_slice(usize::MAX, usize::MAX).num_bits(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_bit_chunks() {
+        assert_eq!(Bitmap::new(0).bit_chunks().count(), 0);
+
+        for len in [1, 63, 64, 65, 130, 256] {
+            let bitmap = Bitmap::ones(len);
+            let chunks = bitmap.bit_chunks().collect::<Vec<_>>();
+
+            assert_eq!(chunks.len(), len.div_ceil(64));
+            assert_eq!(chunks.iter().map(|&(_, n)| n as usize).sum::<usize>(), len);
+            for &(word, valid_bits) in chunks.iter() {
+                assert_eq!(word.count_ones(), valid_bits);
+            }
+        }
+
+        // dirty padding of a shared slice doesn't leak into the last word
+        let bitmap = Bitmap::ones(128).slice(0, 70);
+        let (last, valid_bits) = bitmap.bit_chunks().last().unwrap();
+        assert_eq!(valid_bits, 6);
+        assert_eq!(last, 0b111111);
+    }
+
+    #[test]
+    fn test_union_intersect_extend() {
+        let a_bools = generate(60);
+        let b_bools = generate(130).into_iter().map(|b| !b).collect::<Vec<_>>();
+        let a = Bitmap::from_bools(&a_bools);
+        let b = Bitmap::from_bools(&b_bools);
+
+        let union = (0..130)
+            .map(|i| b_bools[i] || a_bools.get(i).copied().unwrap_or(false))
+            .collect::<Vec<_>>();
+        assert_eq!(a.union_extend(&b), Bitmap::from_bools(&union));
+        assert_eq!(b.union_extend(&a), Bitmap::from_bools(&union));
+
+        let intersection = (0..60)
+            .map(|i| a_bools[i] && b_bools[i])
+            .collect::<Vec<_>>();
+        assert_eq!(a.intersect_extend(&b), Bitmap::from_bools(&intersection));
+        assert_eq!(b.intersect_extend(&a), Bitmap::from_bools(&intersection));
+
+        assert_eq!(a.union_extend(&Bitmap::new(0)), a);
+        assert_eq!(a.intersect_extend(&Bitmap::new(0)).num_bits(), 0);
+    }
+
+    #[test]
+    fn test_try_from_bools() {
+        for len in [0, 1, 7, 8, 64, 130] {
+            let bools = generate(len);
+            assert_eq!(
+                Bitmap::try_from_bools(&bools).unwrap(),
+                Bitmap::from_bools(&bools)
+            );
+        }
+
+        assert_eq!(
+            try_new_buffer(usize::MAX).err(),
+            Some(BuildError::CapacityOverflow {
+                num_bits: usize::MAX
+            })
+        );
+        assert_eq!(
+            try_new_buffer(MAX_BITS + 1).err(),
+            Some(BuildError::CapacityOverflow {
+                num_bits: MAX_BITS + 1
+            })
+        );
+        assert_eq!(
+            Bitmap::try_new(MAX_BITS + 1).err(),
+            Some(BuildError::CapacityOverflow {
+                num_bits: MAX_BITS + 1
+            })
+        );
+        assert_eq!(
+            BuildError::CapacityOverflow { num_bits: 7 }.to_string(),
+            "buffer for 7 bits exceeds the maximum capacity"
+        );
+
+        assert_eq!(Bitmap::try_new(130), Ok(Bitmap::new(130)));
+        assert_eq!(try_new_buffer(0).map(|buf| buf.len()).ok(), Some(0));
+        assert_eq!(try_new_buffer(65).map(|buf| buf.len()).ok(), Some(9));
+
+        // The largest padded size still fits in a `Layout`
+        assert!(MAX_BITS
+            .div_ceil(8)
+            .checked_next_multiple_of(arrs_buffer::ALIGNMENT)
+            .is_some_and(|len| len <= isize::MAX as usize));
+    }
+
+    #[test]
+    fn test_swap_word_endianness() {
+        let bitmap = Bitmap::from_bools(&generate(128));
+        assert_eq!(bitmap.swap_word_endianness().swap_word_endianness(), bitmap);
+
+        let bitmap = Bitmap::from_bools(&generate(130));
+        let swapped = bitmap.swap_word_endianness();
+        assert_eq!(swapped.num_bits(), 192);
+        assert_eq!(swapped.swap_word_endianness().slice(0, 130), bitmap);
+
+        // bit 0 is in the first byte, which becomes the last byte of the word
+        let mut bitmap = Bitmap::new(64);
+        bitmap.set(0);
+        bitmap.set(9);
+        let swapped = bitmap.swap_word_endianness();
+        assert_eq!(swapped.iter_set_bits().collect::<Vec<_>>(), vec![49, 56]);
+        assert!(swapped.get(56).unwrap());
+        assert!(!swapped.get(0).unwrap());
+    }
+
+    #[test]
+    fn test_count_ones_with_block() {
+        for len in [0, 1, 64, 130, 1000] {
+            let bools = generate(len);
+            let bitmap = Bitmap::from_bools(&bools);
+            let expected = bools.iter().filter(|&&b| b).count();
+
+            for block_words in [1, 2, 3, 8, 15, 16, 1000] {
+                assert_eq!(bitmap.count_ones_with_block(block_words), expected);
+            }
+            assert_eq!(bitmap.count_ones(), expected);
+        }
+
+        let bitmap = Bitmap::ones(200).slice(0, 130);
+        assert_eq!(bitmap.count_ones_with_block(1), 130);
+    }
+
+    #[test]
+    fn test_retain_indices() {
+        let indices = [0, 5, 64, 99];
+        let bitmap = Bitmap::from_indices(100, &[99, 5, 0, 64, 5]);
+        assert_eq!(bitmap.to_indices(), indices);
+
+        let bools = generate(130);
+        let mut bitmap = Bitmap::from_bools(&bools);
+        let shared = bitmap.clone();
+
+        let keep = [0, 1, 2, 3, 50, 64, 65, 100, 129, 130, 1000];
+        bitmap.retain_indices(&keep);
+
+        let expected = keep
+            .iter()
+            .copied()
+            .filter(|&i| i < 130 && bools[i])
+            .collect::<Vec<_>>();
+        assert_eq!(bitmap.to_indices(), expected);
+        assert_eq!(shared, Bitmap::from_bools(&bools));
+
+        bitmap.retain_indices(&[]);
+        assert_eq!(bitmap.count_ones(), 0);
+    }
+
+    #[test]
+    fn test_keep_lowest_highest_set() {
+        for len in [0, 1, 63, 64, 65, 130] {
+            let zeros = Bitmap::new(len);
+            assert_eq!(zeros.keep_lowest_set(), zeros);
+            assert_eq!(zeros.keep_highest_set(), zeros);
+        }
+
+        for len in [1, 64, 65, 130, 300] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            if bitmap.count_ones() == 0 {
+                continue;
+            }
+
+            let lowest = bitmap.keep_lowest_set();
+            assert_eq!(lowest.num_bits(), len);
+            assert_eq!(lowest.to_indices(), vec![bitmap.find_first_set().unwrap()]);
+
+            let highest = bitmap.keep_highest_set();
+            assert_eq!(highest.num_bits(), len);
+            assert_eq!(highest.to_indices(), vec![bitmap.find_last_set().unwrap()]);
+        }
+
+        let bitmap = Bitmap::from_indices(200, &[130]);
+        assert_eq!(bitmap.keep_lowest_set(), bitmap);
+        assert_eq!(bitmap.keep_highest_set(), bitmap);
+    }
+
+    #[test]
+    fn test_interleave() {
+        for len in [0, 1, 31, 32, 33, 64, 65, 130] {
+            let a_bools = generate(len);
+            let b_bools = generate(len + 7)[7..].to_vec();
+            let a = Bitmap::from_bools(&a_bools);
+            let b = Bitmap::from_bools(&b_bools);
+
+            let interleaved = a.interleave(&b);
+            let expected = (0..len * 2)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        a_bools[i / 2]
+                    } else {
+                        b_bools[i / 2]
+                    }
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(interleaved, Bitmap::from_bools(&expected));
+
+            assert_eq!(interleaved.deinterleave(), (a, b));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_deinterleave_odd() {
+        Bitmap::new(3).deinterleave();
+    }
+
+    #[test]
+    fn test_try_into_buffer() {
+        let bools = generate(130);
+        let bitmap = Bitmap::from_bools(&bools);
+        let ptr = bitmap.as_byte_slice().as_ptr();
+
+        let buf = bitmap.try_into_buffer().ok().unwrap();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(
+            Bitmap::from_buf(Arc::new(buf), 130),
+            Bitmap::from_bools(&bools)
+        );
+
+        let bitmap = Bitmap::from_bools(&bools);
+        let shared = bitmap.clone();
+        let bitmap = bitmap.try_into_buffer().err().unwrap();
+        assert_eq!(bitmap, shared);
+
+        drop(shared);
+        assert!(bitmap.try_into_buffer().is_ok());
+    }
+
+    #[test]
+    fn test_fill_from_bools() {
+        let mut bitmap = Bitmap::from_bools(&generate(1000));
+        let ptr = bitmap.as_byte_slice().as_ptr();
+
+        for len in [130, 0, 7, 1000, 64, 999] {
+            let bools = generate(len + 3)[3..].to_vec();
+            bitmap.fill_from_bools(&bools);
+            assert_eq!(bitmap, Bitmap::from_bools(&bools));
+            assert_eq!(bitmap.buf().as_ptr(), ptr);
+            assert!(bitmap.validate().is_ok());
+        }
+
+        // too small, reallocates
+        bitmap.fill_from_bools(&generate(5000));
+        assert_eq!(bitmap, Bitmap::from_bools(&generate(5000)));
+
+        // shared, doesn't write into the other bitmap
+        let shared = bitmap.clone();
+        bitmap.fill_from_bools(&[true; 10]);
+        assert_eq!(bitmap, Bitmap::ones(10));
+        assert_eq!(shared, Bitmap::from_bools(&generate(5000)));
+    }
+
+    #[test]
+    fn test_leading_trailing_ones() {
+        for len in [0, 1, 63, 64, 65, 130] {
+            let ones = Bitmap::ones(len);
+            assert_eq!(ones.trailing_ones(), len);
+            assert_eq!(ones.leading_ones(), len);
+
+            let zeros = Bitmap::new(len);
+            assert_eq!(zeros.trailing_ones(), 0);
+            assert_eq!(zeros.leading_ones(), 0);
+        }
+
+        for len in [63, 64, 65] {
+            for zero in [0, 1, len / 2, len - 2, len - 1] {
+                let mut bitmap = Bitmap::ones(len);
+                bitmap.clear(zero);
+                assert_eq!(bitmap.trailing_ones(), zero);
+                assert_eq!(bitmap.leading_ones(), len - zero - 1);
+            }
+        }
+
+        // dirty padding isn't counted
+        let bitmap = Bitmap::ones(128).slice(0, 65);
+        assert_eq!(bitmap.leading_ones(), 65);
+
+        let bools = generate(300);
+        let bitmap = Bitmap::from_bools(&bools);
+        assert_eq!(
+            bitmap.trailing_ones(),
+            bools.iter().take_while(|&&b| b).count()
+        );
+        assert_eq!(
+            bitmap.leading_ones(),
+            bools.iter().rev().take_while(|&&b| b).count()
+        );
+    }
+
+    #[test]
+    fn test_has_single_set_bit() {
+        for len in [0, 1, 64, 130] {
+            assert!(!Bitmap::new(len).has_single_set_bit());
+        }
+
+        for index in [0, 63, 64, 129] {
+            assert!(Bitmap::from_indices(130, &[index]).has_single_set_bit());
+        }
+
+        assert!(!Bitmap::from_indices(130, &[0, 1]).has_single_set_bit());
+        assert!(!Bitmap::from_indices(130, &[3, 129]).has_single_set_bit());
+        assert!(!Bitmap::ones(130).has_single_set_bit());
+
+        // dirty padding isn't counted
+        let mut bitmap = Bitmap::ones(128).slice(0, 65);
+        bitmap.set_range(1, 64, false);
+        assert!(bitmap.has_single_set_bit());
+    }
+
+    #[test]
+    fn test_to_ascii_art() {
+        assert_eq!(Bitmap::new(0).to_ascii_art(4), "    ");
+        assert_eq!(Bitmap::ones(100).to_ascii_art(0), "");
+        assert_eq!(Bitmap::ones(1000).to_ascii_art(10), "█".repeat(10));
+        assert_eq!(Bitmap::new(1000).to_ascii_art(10), " ".repeat(10));
+
+        let art = Bitmap::ones(3).to_ascii_art(6);
+        assert_eq!(art.chars().count(), 6);
+        assert_eq!(art.chars().filter(|&c| c == '█').count(), 3);
+
+        let mut bitmap = Bitmap::new(400);
+        bitmap.set_range(0, 100, true);
+        bitmap.set_range(100, 10, true);
+        bitmap.set_range(200, 50, true);
+        bitmap.set_range(300, 90, true);
+        assert_eq!(bitmap.to_ascii_art(4), "█░▒▓");
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        assert_eq!(bitmap.to_ascii_art(37).chars().count(), 37);
+
+        // Column bounds don't overflow when `width * num_bits` doesn't fit in a usize
+        assert_eq!(mul_div(usize::MAX - 1, MAX_BITS, usize::MAX), MAX_BITS - 1);
+        assert_eq!(mul_div(usize::MAX, MAX_BITS, usize::MAX), MAX_BITS);
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        let mut bitmap = Bitmap::new(100);
+        bitmap.set_range(10, 5, true);
+        bitmap.set_range(17, 3, true);
+        bitmap.set_range(70, 1, true);
+
+        for min_gap in 0..3 {
+            assert_eq!(
+                bitmap.merge_ranges(min_gap),
+                vec![(10, 5), (17, 3), (70, 1)]
+            );
+        }
+        assert_eq!(bitmap.merge_ranges(3), vec![(10, 10), (70, 1)]);
+        assert_eq!(bitmap.merge_ranges(50), vec![(10, 10), (70, 1)]);
+        assert_eq!(bitmap.merge_ranges(51), vec![(10, 61)]);
+
+        assert!(Bitmap::new(100).merge_ranges(3).is_empty());
+    }
+
+    #[test]
+    fn test_intersection_count() {
+        for len in [0, 1, 63, 64, 65, 256, 4097, 10000] {
+            let a_bools = generate(len);
+            let b_bools = generate(len + 11)[11..].to_vec();
+            let a = Bitmap::from_bools(&a_bools);
+            let b = Bitmap::from_bools(&b_bools);
+
+            assert_eq!(a.intersection_count(&b), (&a & &b).count_ones());
+            assert_eq!(a.intersection_count(&a), a.count_ones());
+        }
+
+        // dirty padding isn't counted
+        let a = Bitmap::ones(256).slice(0, 130);
+        assert_eq!(a.intersection_count(&a), 130);
+    }
+
+    #[test]
+    fn test_from_run_iter() {
+        let runs = [
+            (true, 3),
+            (false, 0),
+            (false, 70),
+            (true, 1),
+            (true, 130),
+            (false, 5),
+            (true, 64),
+        ];
+        let bools = runs
+            .iter()
+            .flat_map(|&(value, len)| std::iter::repeat_n(value, len))
+            .collect::<Vec<_>>();
+
+        let bitmap = Bitmap::from_run_iter(runs);
+        assert_eq!(bitmap, Bitmap::from_bools(&bools));
+        assert!(bitmap.validate().is_ok());
+
+        assert_eq!(Bitmap::from_run_iter([]), Bitmap::new(0));
+        assert_eq!(Bitmap::from_run_iter([(false, 100)]), Bitmap::new(100));
+
+        // round trips through iter_runs
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let runs = bitmap
+            .iter_runs()
+            .map(|(value, _, len)| (value, len))
+            .collect::<Vec<_>>();
+        assert_eq!(Bitmap::from_run_iter(runs), bitmap);
+    }
+
+    #[test]
+    fn test_run_lengths() {
+        let bitmap = Bitmap::from_set_ranges(100, &[(0, 3), (10, 70)]);
+        let lengths = bitmap.to_run_lengths();
+        assert_eq!(lengths, vec![0, 3, 7, 70, 20]);
+        assert_eq!(Bitmap::from_run_lengths(&lengths), bitmap);
+
+        let bitmap = Bitmap::from_set_ranges(100, &[(5, 95)]);
+        assert_eq!(bitmap.to_run_lengths(), vec![5, 95]);
+
+        for len in [1, 64, 65, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let lengths = bitmap.to_run_lengths();
+            assert_eq!(lengths.iter().sum::<usize>(), len);
+            assert_eq!(Bitmap::from_run_lengths(&lengths), bitmap);
+        }
+
+        assert!(Bitmap::new(0).to_run_lengths().is_empty());
+        assert_eq!(Bitmap::ones(256).slice(0, 70).to_run_lengths(), vec![0, 70]);
+        assert_eq!(
+            Bitmap::from_run_lengths(&[0, 0, 3, 2]),
+            Bitmap::new(3).concat(&Bitmap::ones(2))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_par_for_each_set_bit() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        for len in [0, 1, 64, 130, 10000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+
+            let visited = (0..len).map(|_| AtomicBool::new(false)).collect::<Vec<_>>();
+            let count = AtomicUsize::new(0);
+            bitmap.par_for_each_set_bit(|i| {
+                assert!(!visited[i].swap(true, Ordering::Relaxed));
+                count.fetch_add(1, Ordering::Relaxed);
+            });
+
+            let indices = (0..len)
+                .filter(|&i| visited[i].load(Ordering::Relaxed))
+                .collect::<Vec<_>>();
+            assert_eq!(indices, bitmap.to_indices());
+            assert_eq!(count.into_inner(), bitmap.count_ones());
+        }
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let bools = generate(300);
+        let mut bitmap = Bitmap::from_bools(&bools);
+        let shared = bitmap.clone();
+
+        let edits = (0..200)
+            .map(|i| ((i * 37) % 320, i % 3 == 0))
+            .collect::<Vec<_>>();
+
+        let mut expected = bitmap.clone();
+        for &(index, value) in edits.iter() {
+            if index >= 300 {
+                continue;
+            }
+            if value {
+                expected.set(index);
+            } else {
+                expected.clear(index);
+            }
+        }
+
+        bitmap.apply_edits(&edits);
+        assert_eq!(bitmap, expected);
+        assert_eq!(shared, Bitmap::from_bools(&bools));
+
+        // last edit of an index wins
+        bitmap.apply_edits(&[(5, true), (5, false), (6, false), (6, true)]);
+        assert!(!bitmap.get(5).unwrap());
+        assert!(bitmap.get(6).unwrap());
+
+        let mut empty = Bitmap::new(0);
+        empty.apply_edits(&[(0, true)]);
+        assert_eq!(empty, Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_and_assign_in_range() {
+        let a = Bitmap::from_bools(&generate(300));
+        let b = Bitmap::from_bools(&generate(313)[13..]);
+        let full = &a & &b;
+
+        for (start, len) in [(0, 0), (0, 300), (3, 60), (64, 64), (70, 200), (299, 1)] {
+            let mut windowed = a.clone();
+            windowed.and_assign_in_range(&b, start, len);
+
+            for i in 0..300 {
+                let expected = if (start..start + len).contains(&i) {
+                    full.get(i)
+                } else {
+                    a.get(i)
+                };
+                assert_eq!(windowed.get(i), expected);
+            }
+        }
+        assert_eq!(a, Bitmap::from_bools(&generate(300)));
+    }
+
+    #[test]
+    fn test_from_packed_with_offset() {
+        let bools = generate(1000);
+        let bytes = Bitmap::from_bools(&bools).as_byte_slice().to_vec();
+
+        for (bit_offset, num_bits) in [(3, 900), (0, 1000), (3, 0), (8, 64), (61, 130), (997, 3)] {
+            let bitmap = Bitmap::from_packed_with_offset(&bytes, bit_offset, num_bits);
+            assert_eq!(
+                bitmap,
+                Bitmap::from_bools(&bools[bit_offset..bit_offset + num_bits])
+            );
+            assert_eq!(bitmap.validate(), Ok(()));
+        }
+
+        // bits past the range in the last byte are dropped
+        let bitmap = Bitmap::from_packed_with_offset(&[0xff, 0xff], 3, 6);
+        assert_eq!(bitmap, Bitmap::ones(6));
+        assert_eq!(bitmap.validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_packed_with_offset_out_of_bounds() {
+        Bitmap::from_packed_with_offset(&[0; 2], 3, 14);
+    }
+
+    #[test]
+    fn test_intersect_with_ranges() {
+        let ranges = [(0, 0), (3, 60), (50, 30), (64, 64), (200, 100), (299, 1)];
+        let mask = Bitmap::from_set_ranges(300, &ranges);
+        for i in 0..300 {
+            let expected = ranges.iter().any(|&(s, l)| (s..s + l).contains(&i));
+            assert_eq!(mask.get(i), Some(expected));
+        }
+
+        let bitmap = Bitmap::from_bools(&generate(300));
+        assert_eq!(bitmap.intersect_with_ranges(&ranges), &bitmap & &mask);
+        assert_eq!(bitmap.intersect_with_ranges(&[]), Bitmap::new(300));
+        assert_eq!(bitmap.intersect_with_ranges(&[(0, 300)]), bitmap);
+
+        let dirty = Bitmap::ones(256).slice(0, 70);
+        assert_eq!(dirty.intersect_with_ranges(&[(60, 10)]).validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_residue_histogram() {
+        let bitmap = Bitmap::from_fn(130, |i| i % 4 == 0);
+        assert_eq!(bitmap.residue_histogram(4), vec![33, 0, 0, 0]);
+        assert_eq!(bitmap.residue_histogram(8), vec![17, 0, 0, 0, 16, 0, 0, 0]);
+        assert_eq!(bitmap.residue_histogram(1), vec![33]);
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let histogram = bitmap.residue_histogram(7);
+        assert_eq!(histogram.len(), 7);
+        assert_eq!(histogram.iter().sum::<usize>(), bitmap.count_ones());
+
+        assert_eq!(Bitmap::new(0).residue_histogram(3), vec![0; 3]);
+    }
+
+    #[test]
+    fn test_clamp_to_length() {
+        let bools = generate(130);
+
+        let mut bitmap = Bitmap::from_bools(&bools);
+        bitmap.clamp_to_length(70);
+        assert_eq!(bitmap, Bitmap::from_bools(&bools[..70]));
+        assert!(bitmap.validate().is_ok());
+
+        bitmap.clamp_to_length(1000);
+        let mut expected = bools[..70].to_vec();
+        expected.resize(1000, false);
+        assert_eq!(bitmap, Bitmap::from_bools(&expected));
+        assert!(bitmap.validate().is_ok());
+
+        // bits set past the end by raw writes are cleared
+        let mut bitmap = Bitmap::new(10);
+        bitmap.as_mut_word_slice()[0] = u64::MAX.to_le();
+        bitmap.clamp_to_length(10);
+        assert_eq!(bitmap, Bitmap::ones(10));
+        assert!(bitmap.validate().is_ok());
+
+        let mut bitmap = Bitmap::new(10);
+        bitmap.as_mut_word_slice()[0] = u64::MAX.to_le();
+        bitmap.clamp_to_length(20);
+        assert_eq!(bitmap.count_ones(), 10);
+        assert!(bitmap.validate().is_ok());
+    }
+
+    #[test]
+    fn test_append_pop_bit() {
+        let bools = generate(300);
+
+        let mut bitmap = Bitmap::new(0);
+        for &b in bools.iter() {
+            bitmap.append_bit(b);
+        }
+        assert_eq!(bitmap, Bitmap::from_bools(&bools));
+
+        for &b in bools[130..].iter().rev() {
+            assert_eq!(bitmap.pop_bit(), Some(b));
+        }
+        assert_eq!(bitmap, Bitmap::from_bools(&bools[..130]));
+        assert_eq!(
+            bitmap.count_ones(),
+            bools[..130].iter().filter(|&&b| b).count()
+        );
+        assert!(bitmap.validate().is_ok());
+
+        let mut bitmap = Bitmap::ones(2);
+        assert_eq!(bitmap.pop_bit(), Some(true));
+        assert_eq!(bitmap.pop_bit(), Some(true));
+        assert_eq!(bitmap.pop_bit(), None);
+
+        // appending over dirty padding of a shared buffer
+        let mut bitmap = Bitmap::ones(128).slice(0, 10);
+        bitmap.append_bit(false);
+        assert_eq!(bitmap.num_bits(), 11);
+        assert_eq!(bitmap.get(10), Some(false));
+    }
+
+    #[test]
+    fn test_nth_set_run() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let ranges = bitmap.set_ranges();
+
+        for k in [0, 1, 2, ranges.len() / 2, ranges.len() - 1] {
+            assert_eq!(bitmap.nth_set_run(k), Some(ranges[k]));
+        }
+        assert_eq!(bitmap.nth_set_run(ranges.len()), None);
+        assert_eq!(bitmap.nth_set_run(usize::MAX), None);
+
+        assert_eq!(Bitmap::ones(130).nth_set_run(0), Some((0, 130)));
+        assert_eq!(Bitmap::ones(130).nth_set_run(1), None);
+        assert_eq!(Bitmap::new(130).nth_set_run(0), None);
+    }
+
+    #[test]
+    fn test_first_run_at_least() {
+        // runs of 1, 3 and 5 set bits
+        let bitmap = Bitmap::from_set_ranges(20, &[(1, 1), (4, 3), (10, 5)]);
+        assert_eq!(bitmap.first_run_at_least(0), Some((1, 1)));
+        assert_eq!(bitmap.first_run_at_least(2), Some((4, 3)));
+        assert_eq!(bitmap.first_run_at_least(4), Some((10, 5)));
+        assert_eq!(bitmap.first_run_at_least(6), None);
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let (start, len) = bitmap.longest_set_run();
+        assert_eq!(bitmap.first_run_at_least(len), Some((start, len)));
+        assert_eq!(Bitmap::new(100).first_run_at_least(0), None);
+        assert_eq!(Bitmap::ones(256).slice(0, 70).first_run_at_least(71), None);
+    }
+
+    #[test]
+    fn test_find_free_range() {
+        let mut bitmap = Bitmap::new(100);
+        assert_eq!(bitmap.find_free_range(100), Some(0));
+        assert_eq!(bitmap.find_free_range(101), None);
+
+        bitmap.set_range(0, 10, true);
+        bitmap.set_range(15, 70, true);
+        assert_eq!(bitmap.find_free_range(3), Some(10));
+        assert_eq!(bitmap.find_free_range(5), Some(10));
+        assert_eq!(bitmap.find_free_range(6), Some(85));
+        assert_eq!(bitmap.find_free_range(15), Some(85));
+        assert_eq!(bitmap.find_free_range(16), None);
+        assert_eq!(bitmap.find_free_range(0), Some(0));
+
+        // padding past `num_bits` isn't free
+        assert_eq!(Bitmap::new(70).find_free_range(71), None);
+        assert_eq!(Bitmap::ones(70).find_free_range(1), None);
+        assert_eq!(Bitmap::new(0).find_free_range(1), None);
+    }
+
+    #[test]
+    fn test_alloc() {
+        let mut bitmap = Bitmap::new(100);
+
+        assert_eq!(bitmap.alloc(10), Some(0));
+        assert_eq!(bitmap.alloc(20), Some(10));
+        assert_eq!(bitmap.alloc(30), Some(30));
+        assert_eq!(bitmap.count_zeros(), 40);
+
+        bitmap.free(10, 20);
+        assert_eq!(bitmap.count_zeros(), 60);
+        assert_eq!(bitmap.alloc(5), Some(10));
+        assert_eq!(bitmap.alloc(30), Some(60));
+        assert_eq!(bitmap.alloc(16), None);
+        assert_eq!(bitmap.alloc(15), Some(15));
+        assert_eq!(bitmap.count_zeros(), 10);
+        assert_eq!(bitmap.validate(), Ok(()));
+
+        bitmap.free(0, 100);
+        assert_eq!(bitmap.count_zeros(), 100);
+        assert_eq!(bitmap.alloc(101), None);
+
+        let shared = Bitmap::new(70);
+        let mut allocated = shared.clone();
+        assert_eq!(allocated.alloc(70), Some(0));
+        assert_eq!(shared.count_zeros(), 70);
+        assert_eq!(Bitmap::ones(256).slice(0, 70).count_zeros(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_free_out_of_bounds() {
+        Bitmap::new(100).free(90, 11);
+    }
+
+    #[test]
+    fn test_xor_rows() {
+        let rows = [
+            [true, false, true, true, false],
+            [true, true, false, true, false],
+            [false, true, false, true, true],
+        ];
+        let bools = rows.concat();
+        let bitmap = Bitmap::from_bools(&bools);
+        assert_eq!(
+            bitmap.xor_rows(5),
+            Bitmap::from_bools(&[false, false, true, true, true])
+        );
+
+        // last row is partial
+        let bitmap = Bitmap::from_bools(&bools[..13]);
+        assert_eq!(
+            bitmap.xor_rows(5),
+            Bitmap::from_bools(&[false, false, true, false, false])
+        );
+
+        for (len, width) in [(1000, 64), (1000, 100), (1000, 130), (130, 200), (0, 3)] {
+            let bools = generate(len);
+            let bitmap = Bitmap::from_bools(&bools);
+            let expected = (0..width)
+                .map(|col| (col..len).step_by(width).filter(|&i| bools[i]).count() % 2 == 1)
+                .collect::<Vec<_>>();
+            assert_eq!(bitmap.xor_rows(width), Bitmap::from_bools(&expected));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_assert_invariants() {
+        let mut buf = Buffer::new(16);
+        buf.as_mut_slice().fill(0xff);
+        let mut bitmap = Bitmap::from_buf(Arc::new(buf), 70);
+
+        let err = std::panic::catch_unwind(|| bitmap.assert_invariants()).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<String>().unwrap(),
+            "bitmap invariant violated: 58 padding bits are set"
+        );
+
+        bitmap.clear_padding_bits();
+        bitmap.assert_invariants();
+
+        let bitmap = unsafe { Bitmap::from_buf_unchecked(Arc::new(Buffer::new(1)), 70) };
+        assert!(std::panic::catch_unwind(|| bitmap.assert_invariants()).is_err());
+
+        // In place operations keep the padding of a dirty shared buffer as it is
+        let ones = Bitmap::ones(128);
+        let mut dirty = ones.slice(0, 70);
+        dirty.clear(3);
+        dirty.set_range(10, 60, false);
+        dirty.copy_bits_from(0, &ones, 0, 70);
+        assert_eq!(dirty.last_word_padding(), u64::MAX << 6);
+        dirty ^= &Bitmap::ones(70);
+        assert_eq!(dirty.last_word_padding(), 0);
+
+        // but setting a new padding bit is caught
+        let mut bitmap = Bitmap::new(70);
+        let padding = bitmap.last_word_padding();
+        bitmap.as_mut_word_slice()[1] |= (1u64 << 10).to_le();
+        let err = std::panic::catch_unwind(|| bitmap.assert_padding_kept(padding)).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<String>().unwrap(),
+            "bitmap invariant violated: 1 padding bits were set"
+        );
+    }
+
+    #[test]
+    fn test_pad_to_word_boundary() {
+        for len in [0, 1, 9, 64, 65, 130] {
+            let bools = generate(len);
+            let mut bitmap = Bitmap::from_bools(&bools);
+            bitmap.pad_to_word_boundary();
+
+            assert_eq!(bitmap.capacity_bytes() % 8, 0);
+            assert!(bitmap.capacity_bytes() >= len.div_ceil(64) * 8);
+            assert_eq!(bitmap, Bitmap::from_bools(&bools));
+            assert!(bitmap.validate().is_ok());
+        }
+
+        let mut bitmap = Bitmap::ones(128).slice(0, 65);
+        bitmap.pad_to_word_boundary();
+        assert_eq!(bitmap.capacity_bytes(), 16);
+        assert_eq!(bitmap, Bitmap::ones(65));
+        assert!(bitmap.validate().is_ok());
+    }
+
+    #[test]
+    fn test_distinct_set_bits() {
+        let bitmaps = (0..5)
+            .map(|i| Bitmap::from_bools(&generate(1000 + i * 3)[i * 3..]))
+            .collect::<Vec<_>>();
+
+        for n in 0..=bitmaps.len() {
+            assert_eq!(
+                Bitmap::distinct_set_bits(&bitmaps[..n]),
+                Bitmap::union_all(&bitmaps[..n]).count_ones()
+            );
+        }
+
+        let dirty = Bitmap::ones(256).slice(0, 70);
+        assert_eq!(Bitmap::distinct_set_bits(&[dirty, Bitmap::new(70)]), 70);
+    }
+
+    #[test]
+    fn test_merge_set_bits() {
+        let bitmaps = (0..5)
+            .map(|i| Bitmap::from_bools(&generate(300 + i * 3)[i * 3..]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Bitmap::merge_set_bits(&bitmaps).collect::<Vec<_>>(),
+            Bitmap::union_all(&bitmaps).to_indices()
+        );
+
+        let same = [bitmaps[0].clone(), bitmaps[0].clone()];
+        assert_eq!(
+            Bitmap::merge_set_bits(&same).collect::<Vec<_>>(),
+            bitmaps[0].to_indices()
+        );
+
+        let mixed = [
+            Bitmap::from_indices(10, &[3, 9]),
+            Bitmap::from_indices(200, &[0, 3, 150]),
+        ];
+        assert_eq!(
+            Bitmap::merge_set_bits(&mixed).collect::<Vec<_>>(),
+            vec![0, 3, 9, 150]
+        );
+        assert_eq!(Bitmap::merge_set_bits(&[]).next(), None);
+    }
+
+    #[test]
+    fn test_union_all_or_with_iter() {
+        let bitmaps = (0..5)
+            .map(|i| Bitmap::from_bools(&generate(130 + i * 3)[i * 3..]))
+            .collect::<Vec<_>>();
+
+        let expected = bitmaps[1..]
+            .iter()
+            .fold(bitmaps[0].clone(), |acc, b| &acc | b);
+        assert_eq!(Bitmap::union_all(&bitmaps), expected);
+        assert_eq!(Bitmap::union_all(&[]), Bitmap::new(0));
+        assert_eq!(Bitmap::union_all(&bitmaps[..1]), bitmaps[0]);
+
+        let initial = Bitmap::from_indices(130, &[0, 1, 2, 129]);
+        let mut acc = initial.clone();
+        acc.or_with_iter(bitmaps.iter());
+
+        let mut all = bitmaps.clone();
+        all.push(initial.clone());
+        assert_eq!(acc, Bitmap::union_all(&all));
+        assert_eq!(initial, Bitmap::from_indices(130, &[0, 1, 2, 129]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_or_with_iter_length_mismatch() {
+        let mut bitmap = Bitmap::new(10);
+        bitmap.or_with_iter([&Bitmap::new(10), &Bitmap::new(11)]);
+    }
+
+    #[test]
+    fn test_count_ones_u64() {
+        for len in [0, 1, 64, 130, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            assert_eq!(bitmap.count_ones_u64(), bitmap.count_ones() as u64);
+        }
+        assert_eq!(Bitmap::ones(1000).count_ones_u64(), 1000);
+        assert_eq!(Bitmap::ones(256).slice(0, 130).count_ones_u64(), 130);
+    }
+
+    // A 32 bit target can't allocate a bitmap with more than `u32::MAX` set bits, so the wide
+    // accumulator is fed the words directly.
+    #[test]
+    #[cfg(target_pointer_width = "32")]
+    fn test_count_ones_u64_wide_accumulator() {
+        let num_words = (1 << 26) + 1;
+        let count = count_ones_wide(std::iter::repeat(u64::MAX).take(num_words));
+        assert_eq!(count, num_words as u64 * 64);
+        assert!(count > usize::MAX as u64);
+    }
+
+    #[test]
+    fn test_parity() {
+        for len in [0, 1, 63, 64, 65, 130, 1000, 1001] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            assert_eq!(bitmap.parity(), bitmap.count_ones() % 2 == 1);
+        }
+        assert!(!Bitmap::new(100).parity());
+        assert!(Bitmap::ones(101).parity());
+        assert!(!Bitmap::ones(256).slice(0, 130).parity());
+    }
+
+    #[test]
+    fn test_fold_xor_u64() {
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let digest = bitmap.fold_xor_u64();
+        assert_eq!(Bitmap::from_bools(&generate(1000)).fold_xor_u64(), digest);
+
+        for i in [0, 1, 63, 64, 500, 999] {
+            let mut changed = bitmap.clone();
+            if changed.is_set(i) {
+                changed.clear(i);
+            } else {
+                changed.set(i);
+            }
+            assert_ne!(changed.fold_xor_u64(), digest);
+        }
+
+        assert_eq!(Bitmap::new(0).fold_xor_u64(), 0);
+        assert_eq!(
+            Bitmap::ones(256).slice(0, 70).fold_xor_u64(),
+            Bitmap::ones(70).fold_xor_u64()
+        );
+    }
+
+    #[test]
+    fn test_entropy_bits_per_bit() {
+        let random = Bitmap::from_bools(&generate(10_000));
+        assert!((random.entropy_bits_per_bit() - 1.0).abs() < 0.001);
+
+        assert_eq!(Bitmap::new(1000).entropy_bits_per_bit(), 0.0);
+        assert_eq!(Bitmap::ones(1000).entropy_bits_per_bit(), 0.0);
+        assert_eq!(Bitmap::new(0).entropy_bits_per_bit(), 0.0);
+
+        let quarter = Bitmap::from_fn(1000, |i| i % 4 == 0);
+        assert!((quarter.entropy_bits_per_bit() - 0.8113).abs() < 0.001);
+        assert_eq!(
+            Bitmap::from_fn(1000, |i| i < 500).entropy_bits_per_bit(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_trim() {
+        let bitmap = Bitmap::from_indices(300, &[70, 71, 100, 250]);
+        let (offset, trimmed) = bitmap.trim();
+        assert_eq!(offset, 70);
+        assert_eq!(trimmed.num_bits(), 181);
+        assert_eq!(
+            trimmed
+                .iter_set_bits()
+                .map(|i| i + offset)
+                .collect::<Vec<_>>(),
+            bitmap.to_indices()
+        );
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let (offset, trimmed) = bitmap.trim();
+        assert!(trimmed.get(0).unwrap());
+        assert!(trimmed.get(trimmed.num_bits() - 1).unwrap());
+        let mut restored = Bitmap::new(1000);
+        restored.copy_bits_from(offset, &trimmed, 0, trimmed.num_bits());
+        assert_eq!(restored, bitmap);
+
+        assert_eq!(Bitmap::new(100).trim(), (0, Bitmap::new(0)));
+        assert_eq!(Bitmap::ones(100).trim(), (0, Bitmap::ones(100)));
+    }
+
+    #[test]
+    #[should_panic(expected = "bitmap too large: requested 18446744073709551615 bits")]
+    #[cfg(target_pointer_width = "64")]
+    fn test_new_too_large() {
+        Bitmap::new(usize::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "bitmap too large")]
+    fn test_ones_too_large() {
+        Bitmap::ones(MAX_BITS + 1);
+    }
+
+    #[test]
+    fn test_iter_nonzero_words() {
+        let indices = (0..100_000).step_by(1000).collect::<Vec<_>>();
+        let bitmap = Bitmap::from_indices(100_000, &indices);
+
+        let words = bitmap.iter_nonzero_words().collect::<Vec<_>>();
+        let expected = indices
+            .iter()
+            .map(|&i| (i / 64, 1 << (i % 64)))
+            .collect::<Vec<_>>();
+        assert_eq!(words, expected);
+
+        assert_eq!(Bitmap::new(1000).iter_nonzero_words().count(), 0);
+        // dirty padding doesn't make the last word non-zero
+        let mut bitmap = Bitmap::ones(128).slice(0, 65);
+        bitmap.clear(64);
+        assert_eq!(
+            bitmap.iter_nonzero_words().collect::<Vec<_>>(),
+            vec![(0, u64::MAX)]
+        );
+    }
+
+    #[test]
+    fn test_replace_range() {
+        let bools = generate(300);
+        let patch_bools = generate(200)[..130].iter().map(|b| !b).collect::<Vec<_>>();
+        let patch = Bitmap::from_bools(&patch_bools);
+
+        for start in [0, 3, 64, 170] {
+            let mut bitmap = Bitmap::from_bools(&bools);
+            bitmap.replace_range(start, &patch);
+
+            let mut expected = bools.clone();
+            expected[start..start + 130].copy_from_slice(&patch_bools);
+            assert_eq!(bitmap, Bitmap::from_bools(&expected));
+        }
+
+        let mut bitmap = Bitmap::from_bools(&bools);
+        bitmap.replace_range(300, &Bitmap::new(0));
+        assert_eq!(bitmap, Bitmap::from_bools(&bools));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_replace_range_out_of_bounds() {
+        Bitmap::new(100).replace_range(50, &Bitmap::new(51));
+    }
+
+    #[test]
+    fn test_longest_runs() {
+        let mut bitmap = Bitmap::new(40);
+        bitmap.set_range(1, 3, true);
+        bitmap.set_range(10, 5, true);
+        bitmap.set_range(20, 2, true);
+        bitmap.set_range(30, 5, true);
+        assert_eq!(bitmap.longest_set_run(), (10, 5));
+        assert_eq!(bitmap.longest_zero_run(), (22, 8));
+
+        assert_eq!(Bitmap::new(0).longest_set_run(), (0, 0));
+        assert_eq!(Bitmap::new(0).longest_zero_run(), (0, 0));
+        assert_eq!(Bitmap::new(100).longest_set_run(), (0, 0));
+        assert_eq!(Bitmap::new(100).longest_zero_run(), (0, 100));
+        assert_eq!(Bitmap::ones(100).longest_set_run(), (0, 100));
+        assert_eq!(Bitmap::ones(100).longest_zero_run(), (0, 0));
+
+        // bounded by num_bits even with dirty padding
+        assert_eq!(Bitmap::ones(128).slice(0, 70).longest_set_run(), (0, 70));
+    }
+
+    #[test]
+    fn test_gather() {
+        let bitmap = Bitmap::from_bools(&generate(300));
+        let indices = (0..300).map(|i| (i * 7) % 300).collect::<Vec<_>>();
+
+        let gathered = bitmap.gather(&indices);
+        assert_eq!(gathered.num_bits(), 300);
+        for (k, &index) in indices.iter().enumerate() {
+            assert_eq!(gathered.get(k), bitmap.get(index));
+        }
+        assert_eq!(gathered.count_ones(), bitmap.count_ones());
+
+        let gathered = Bitmap::ones(10).gather(&[9, 10, 0, 1000, 9]);
+        assert_eq!(
+            gathered,
+            Bitmap::from_bools(&[true, false, true, false, true])
+        );
+        assert_eq!(bitmap.gather(&[]), Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_scatter() {
+        let source = Bitmap::from_bools(&generate(500));
+        let indices = (0..200).map(|i| (i * 13) % 500).collect::<Vec<_>>();
+
+        let compact = source.gather(&indices);
+        let scattered = compact.scatter(&indices, 500);
+        assert_eq!(scattered.gather(&indices), compact);
+        for i in 0..500 {
+            let expected = indices.contains(&i) && source.is_set(i);
+            assert_eq!(scattered.get(i), Some(expected));
+        }
+
+        // later duplicates win, out of range targets are skipped
+        let bits = Bitmap::from_bools(&[true, false, false, true, true]);
+        let scattered = bits.scatter(&[2, 2, 7, 4, 100], 8);
+        assert_eq!(scattered.to_indices(), vec![4]);
+        let scattered = bits.scatter(&[2, 0, 2, 4, 5], 8);
+        assert_eq!(scattered.to_indices(), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_byte_word_at() {
+        let bools = generate(130);
+        let bitmap = Bitmap::from_bools(&bools);
+        for (i, word) in bitmap.iter_words().enumerate() {
+            assert_eq!(bitmap.word_at(i), word);
+        }
+        for (i, &byte) in bitmap.as_byte_slice().iter().enumerate() {
+            assert_eq!(bitmap.byte_at(i), byte);
+        }
+
+        // dirty padding is masked in the last unit
+        let bitmap = Bitmap::ones(256).slice(0, 70);
+        assert_eq!(bitmap.word_at(0), u64::MAX);
+        assert_eq!(bitmap.word_at(1), 0b111111);
+        assert_eq!(bitmap.word_at(2), 0);
+        assert_eq!(bitmap.word_at(usize::MAX), 0);
+        assert_eq!(bitmap.byte_at(7), 0xff);
+        assert_eq!(bitmap.byte_at(8), 0b111111);
+        assert_eq!(bitmap.byte_at(9), 0);
+        assert_eq!(bitmap.byte_at(usize::MAX), 0);
+
+        assert_eq!(Bitmap::ones(64).byte_at(7), 0xff);
+        assert_eq!(Bitmap::new(0).word_at(0), 0);
+        assert_eq!(Bitmap::new(0).byte_at(0), 0);
+    }
+
+    #[test]
+    fn test_boolean_fn_counts() {
+        type Reference = fn(bool, bool) -> bool;
+        let cases: [(&str, Reference); 6] = [
+            ("union", |a, b| a | b),
+            ("difference", |a, b| a & !b),
+            ("or_not", |a, b| a | !b),
+            ("nand", |a, b| !(a & b)),
+            ("nor", |a, b| !(a | b)),
+            ("xnor", |a, b| !(a ^ b)),
+        ];
+
+        for len in [0, 1, 63, 64, 65, 130, 1000] {
+            let a_bools = generate(len);
+            let b_bools = generate(len + 5)[5..].to_vec();
+            let a = Bitmap::from_bools(&a_bools);
+            let b = Bitmap::from_bools(&b_bools);
+
+            for (name, reference) in cases {
+                let expected = a_bools
+                    .iter()
+                    .zip(b_bools.iter())
+                    .filter(|(&x, &y)| reference(x, y))
+                    .count();
+                let count = match name {
+                    "union" => a.union_count(&b),
+                    "difference" => a.difference_count(&b),
+                    "or_not" => a.or_not_count(&b),
+                    "nand" => a.nand_count(&b),
+                    "nor" => a.nor_count(&b),
+                    "xnor" => a.xnor_count(&b),
+                    _ => unreachable!(),
+                };
+                assert_eq!(count, expected, "{} of {} bits", name, len);
+            }
+
+            assert_eq!(
+                a.boolean_fn_count(&b, |x, y| x & y),
+                a.intersection_count(&b)
+            );
+        }
+    }
+
+    #[test]
+    fn test_estimate_union_count() {
+        let a = Bitmap::from_bools(&generate(1_000_000));
+        let b = Bitmap::from_bools(&generate(1_000_100)[100..]);
+        let exact = a.union_count(&b) as f64;
+
+        for sample_words in [256, 1024, 4096] {
+            let estimate = a.estimate_union_count(&b, sample_words) as f64;
+            assert!(
+                (estimate - exact).abs() / exact < 0.02,
+                "{} vs {}",
+                estimate,
+                exact
+            );
+        }
+
+        let small = Bitmap::from_bools(&generate(130));
+        assert_eq!(
+            small.estimate_union_count(&small, 3),
+            small.union_count(&small)
+        );
+        assert_eq!(Bitmap::new(0).estimate_union_count(&Bitmap::new(0), 1), 0);
+
+        // The last sampled word index stays in bounds when `i * num_words` overflows a usize
+        let num_words = MAX_BITS.div_ceil(64);
+        let sample_words = num_words - 1;
+        assert_eq!(
+            mul_div(sample_words - 1, num_words, sample_words),
+            num_words - 2
+        );
+    }
+
+    #[test]
+    fn test_for_each_word_pair() {
+        for len in [0, 1, 64, 130, 1000] {
+            let a = Bitmap::from_bools(&generate(len));
+            let b = Bitmap::from_bools(&generate(len + 7)[7..]);
+
+            let mut count = 0;
+            let mut next_index = 0;
+            a.for_each_word_pair(&b, |i, x, y| {
+                assert_eq!(i, next_index);
+                next_index += 1;
+                count += (x & y).count_ones() as usize;
+            });
+            assert_eq!(count, a.intersection_count(&b));
+            assert_eq!(next_index, len.div_ceil(64));
+        }
+
+        let dirty = Bitmap::ones(256).slice(0, 70);
+        let mut last = (0, 0);
+        dirty.for_each_word_pair(&dirty, |_, x, y| last = (x, y));
+        assert_eq!(last, (0b11_1111, 0b11_1111));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_for_each_word_pair_length_mismatch() {
+        Bitmap::new(10).for_each_word_pair(&Bitmap::new(11), |_, _, _| {});
+    }
+
+    #[test]
+    fn test_into_vec_u8() {
+        for len in [0, 1, 8, 9, 130] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let bytes = bitmap.as_byte_slice().to_vec();
+            let num_bytes = bitmap.num_bytes();
+
+            let vec = bitmap.into_vec_u8();
+            assert_eq!(vec.len(), num_bytes);
+            assert_eq!(vec.len(), len.div_ceil(8));
+            assert_eq!(vec, bytes);
+        }
+
+        let vec = Bitmap::ones(64).slice(0, 9).into_vec_u8();
+        assert_eq!(vec, vec![0xff, 0b1]);
+    }
+
+    #[test]
+    fn test_set_ranges_into() {
+        let mut out = vec![(1, 1); 3];
+
+        for len in [1000, 0, 130, 64, 1000] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            bitmap.set_ranges_into(&mut out);
+            assert_eq!(out, bitmap.set_ranges());
+        }
+
+        // dirty padding isn't reported
+        let capacity = out.capacity();
+        Bitmap::ones(256).slice(0, 70).set_ranges_into(&mut out);
+        assert_eq!(out, vec![(0, 70)]);
+        assert_eq!(out.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_shift() {
+        for len in [0, 1, 63, 64, 65, 130, 300] {
+            let bools = generate(len);
+            let bitmap = Bitmap::from_bools(&bools);
+
+            for n in [0, 1, 5, 63, 64, 65, 129, 300, 1000] {
+                let left = (0..len).map(|i| i >= n && bools[i - n]).collect::<Vec<_>>();
+                assert_eq!(bitmap.shift_left(n), Bitmap::from_bools(&left));
+
+                let right = (0..len)
+                    .map(|i| i + n < len && bools[i + n])
+                    .collect::<Vec<_>>();
+                assert_eq!(bitmap.shift_right(n), Bitmap::from_bools(&right));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shift_in_place() {
+        for len in [0, 1, 63, 64, 65, 130, 300] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+
+            for n in [0, 1, 5, 63, 64, 65, 129, 300, 1000] {
+                let mut left = bitmap.clone();
+                left.shift_left_in_place(n);
+                assert_eq!(left, bitmap.shift_left(n));
+                assert_eq!(left.validate(), Ok(()));
+
+                let mut right = bitmap.clone();
+                right.shift_right_in_place(n);
+                assert_eq!(right, bitmap.shift_right(n));
+                assert_eq!(right.validate(), Ok(()));
+            }
+        }
+
+        // dirty padding isn't shifted in
+        let mut bitmap = Bitmap::ones(256).slice(0, 70);
+        bitmap.shift_right_in_place(1);
+        assert_eq!(bitmap, Bitmap::ones(69).concat(&Bitmap::new(1)));
+    }
+
+    #[test]
+    fn test_advance() {
+        // window over the last 100 time steps, bit `i` is an event at `base + i`
+        let events = generate(1000);
+        let mut window = Bitmap::from_bools(&events[..100]);
+        let mut base = 0;
+
+        for k in [1, 10, 63, 64, 65, 100, 150] {
+            window.advance(k);
+            base += k;
+            let top = 100usize.saturating_sub(k);
+            for i in top..100 {
+                if events[base + i] {
+                    window.set(i);
+                }
+            }
+
+            assert_eq!(window, Bitmap::from_bools(&events[base..base + 100]));
+            assert_eq!(window.validate(), Ok(()));
+        }
+
+        let shared = Bitmap::ones(70);
+        let mut advanced = shared.clone();
+        advanced.advance(10);
+        assert_eq!(advanced, Bitmap::ones(60).concat(&Bitmap::new(10)));
+        assert_eq!(shared, Bitmap::ones(70));
+    }
+
+    #[test]
+    fn test_shift_right_and() {
+        for len in [0, 1, 64, 130, 300] {
+            let bitmap = Bitmap::from_bools(&generate(len));
+            let mask = Bitmap::from_bools(&generate(len + 9)[9..]);
+
+            for n in [0, 1, 63, 64, 65, 200] {
+                assert_eq!(
+                    bitmap.shift_right_and(n, &mask),
+                    &bitmap.shift_right(n) & &mask
+                );
+            }
+        }
+
+        // dirty padding isn't shifted in
+        let bitmap = Bitmap::ones(256).slice(0, 70);
+        assert_eq!(
+            bitmap.shift_right_and(1, &Bitmap::ones(70)),
+            Bitmap::ones(69).concat(&Bitmap::new(1))
+        );
+    }
+
+    #[test]
+    fn test_to_containers() {
+        let indices = [0, 5, 65_535, 65_536, 70_000, 200_000, 262_143];
+        let bitmap = Bitmap::from_indices(262_144, &indices);
+
+        let containers = bitmap.to_containers();
+        assert_eq!(
+            containers,
+            vec![
+                (0, vec![0, 5, 65_535]),
+                (1, vec![0, 4464]),
+                (3, vec![3392, 65_535]),
+            ]
+        );
+
+        let decoded = containers
+            .iter()
+            .flat_map(|(high, lows)| {
+                lows.iter()
+                    .map(move |&low| (usize::from(*high) << 16) | usize::from(low))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, indices);
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let containers = bitmap.to_containers();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(
+            containers[0]
+                .1
+                .iter()
+                .map(|&i| usize::from(i))
+                .collect::<Vec<_>>(),
+            bitmap.to_indices()
+        );
+        assert!(Bitmap::new(100_000).to_containers().is_empty());
+    }
+
+    #[test]
+    fn test_set_bit_deltas() {
+        let bitmap = Bitmap::from_indices(30, &[3, 7, 20]);
+        assert_eq!(bitmap.set_bit_deltas().collect::<Vec<_>>(), vec![3, 4, 13]);
+
+        let bitmap = Bitmap::from_indices(200, &[0, 1, 64, 199]);
+        assert_eq!(
+            bitmap.set_bit_deltas().collect::<Vec<_>>(),
+            vec![0, 1, 63, 135]
+        );
+
+        let bitmap = Bitmap::from_bools(&generate(1000));
+        let mut index = 0;
+        let decoded = bitmap
+            .set_bit_deltas()
+            .map(|delta| {
+                index += delta;
+                index
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(decoded, bitmap.to_indices());
+
+        assert_eq!(Bitmap::new(100).set_bit_deltas().count(), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "byte 8 is out of bounds for buffer of 2 bytes")]
+    fn test_get_under_allocated() {
+        let bitmap = unsafe { Bitmap::from_buf_unchecked(Arc::new(Buffer::new(2)), 100) };
+        assert_eq!(bitmap.get(15), Some(false));
+        bitmap.get(64);
+    }
+
+    #[test]
+    fn test_combine_n() {
+        let bools = (0..3)
+            .map(|i| generate(130 + i * 7)[i * 7..].to_vec())
+            .collect::<Vec<_>>();
+        let bitmaps = bools
+            .iter()
+            .map(|b| Bitmap::from_bools(b))
+            .collect::<Vec<_>>();
+
+        let majority =
+            Bitmap::combine_n(&bitmaps, |w| (w[0] & w[1]) | (w[0] & w[2]) | (w[1] & w[2]));
+        let expected = (0..130)
+            .map(|i| bools.iter().filter(|b| b[i]).count() >= 2)
+            .collect::<Vec<_>>();
+        assert_eq!(majority, Bitmap::from_bools(&expected));
+
+        // padding is masked even if `f` sets it
+        assert_eq!(Bitmap::combine_n(&bitmaps, |_| u64::MAX), Bitmap::ones(130));
+        assert_eq!(Bitmap::combine_n(&[], |_| u64::MAX), Bitmap::new(0));
+    }
+
+    #[test]
+    fn test_majority3() {
+        // every combination of inputs at every offset within a word
+        let len = 8 * 33;
+        let input = |bit: usize| Bitmap::from_fn(len, |i| (i % 8) & (1 << bit) != 0);
+        let (a, b, c) = (input(0), input(1), input(2));
+        let expected = Bitmap::from_fn(len, |i| (i % 8).count_ones() >= 2);
+        assert_eq!(Bitmap::majority3(&a, &b, &c), expected);
+
+        let bitmaps = (0..3)
+            .map(|i| Bitmap::from_bools(&generate(1000 + i * 7)[i * 7..]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Bitmap::majority3(&bitmaps[0], &bitmaps[1], &bitmaps[2]),
+            Bitmap::combine_n(&bitmaps, |w| (w[0] & w[1]) | (w[0] & w[2]) | (w[1] & w[2]))
+        );
+
+        let dirty = Bitmap::ones(256).slice(0, 70);
+        let majority = Bitmap::majority3(&dirty, &dirty, &Bitmap::new(70));
+        assert_eq!(majority, Bitmap::ones(70));
+        assert_eq!(majority.validate(), Ok(()));
     }
 
     #[test]
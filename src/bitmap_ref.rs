@@ -0,0 +1,138 @@
+use arrs_buffer::Buffer;
+
+use crate::bitmap::set_bit_positions;
+use crate::Bitmap;
+
+/// A read-only view into a range of bits of a [Bitmap], see [Bitmap::view].
+///
+/// Unlike [Bitmap::slice] creating a view doesn't allocate or touch the reference count of the
+///  underlying buffer. Reads shift the bits by the offset of the view on the fly instead.
+#[derive(Clone, Copy)]
+pub struct BitmapRef<'a> {
+    buf: &'a Buffer,
+    bit_offset: usize,
+    num_bits: usize,
+}
+
+impl<'a> BitmapRef<'a> {
+    /// The buffer must hold at least `(bit_offset + num_bits).div_ceil(8)` bytes.
+    pub(crate) fn new(buf: &'a Buffer, bit_offset: usize, num_bits: usize) -> Self {
+        debug_assert!((bit_offset + num_bits).div_ceil(8) <= buf.len());
+
+        Self {
+            buf,
+            bit_offset,
+            num_bits,
+        }
+    }
+
+    /// Number of bits in this view
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    /// Offset of the first bit of the view in the underlying buffer
+    pub fn bit_offset(&self) -> usize {
+        self.bit_offset
+    }
+
+    /// Returns the bit at given index relative to the start of the view.
+    pub fn get(&self, bit_index: usize) -> Option<bool> {
+        if bit_index >= self.num_bits {
+            return None;
+        }
+
+        let index = self.bit_offset + bit_index;
+        Some(self.buf.as_slice()[index / 8] & (1 << (index % 8)) != 0)
+    }
+
+    /// Number of set bits in the view
+    pub fn count_ones(&self) -> usize {
+        (0..self.num_words())
+            .map(|i| self.word(i).count_ones() as usize)
+            .sum()
+    }
+
+    /// Iterates over the indices of set bits in ascending order, relative to the start of the view.
+    pub fn iter_set_bits(&self) -> impl Iterator<Item = usize> + 'a {
+        let view = *self;
+        (0..self.num_words()).flat_map(move |i| set_bit_positions(view.word(i), i * 64))
+    }
+
+    /// Copies the bits of the view into a new bitmap, same as [Bitmap::slice].
+    pub fn to_bitmap(&self) -> Bitmap {
+        Bitmap::from_word_fn(self.num_bits, |i| self.word(i))
+    }
+
+    /// Number of 64 bit words needed to hold `num_bits`
+    fn num_words(&self) -> usize {
+        self.num_bits.div_ceil(64)
+    }
+
+    /// Reads the bits `[64 * word_index, 64 * word_index + 64)` of the view, bits past `num_bits`
+    ///  are masked out.
+    ///
+    /// The buffer is padded to 64 bytes so the words holding the first and last bits of the view
+    ///  can always be read whole.
+    fn word(&self, word_index: usize) -> u64 {
+        debug_assert!(word_index < self.num_words());
+
+        let start = self.bit_offset + word_index * 64;
+        let end = self.bit_offset + self.num_bits;
+        let src_index = start / 64;
+        let shift = start % 64;
+
+        let ptr = self.buf.as_ptr() as *const u64;
+        let mut word = unsafe { u64::from_le(*ptr.add(src_index)) } >> shift;
+        if shift != 0 && (src_index + 1) * 64 < end {
+            word |= unsafe { u64::from_le(*ptr.add(src_index + 1)) } << (64 - shift);
+        }
+
+        let valid_bits = end - start;
+        if valid_bits < 64 {
+            word & ((1 << valid_bits) - 1)
+        } else {
+            word
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_matches_slice() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let bools = (0..1000).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let bitmap = Bitmap::from_bools(&bools);
+
+        for (start, len) in [
+            (0, 0),
+            (0, 1000),
+            (1, 63),
+            (3, 64),
+            (63, 2),
+            (64, 128),
+            (100, 500),
+            (999, 1),
+            (1000, 0),
+        ] {
+            let view = bitmap.view(start, len);
+            let slice = bitmap.slice(start, len);
+
+            assert_eq!(view.num_bits(), len);
+            assert_eq!(view.to_bitmap(), slice);
+            assert_eq!(view.count_ones(), slice.count_ones());
+            assert_eq!(view.iter_set_bits().collect::<Vec<_>>(), slice.to_indices());
+            for i in 0..=len {
+                assert_eq!(view.get(i), slice.get(i));
+            }
+        }
+
+        assert_eq!(Bitmap::ones(130).view(100, 30).count_ones(), 30);
+        assert_eq!(Bitmap::new(0).view(0, 0).count_ones(), 0);
+    }
+}
@@ -0,0 +1,59 @@
+use bitvec::field::BitField;
+use bitvec::order::Lsb0;
+use bitvec::slice::BitSlice;
+use bitvec::vec::BitVec;
+
+use crate::Bitmap;
+
+impl Bitmap {
+    /// Create a bitmap from a [BitSlice], bit `i` of the slice becomes bit `i` of the bitmap.
+    ///
+    /// `Lsb0` over `u8` is the same layout as the bitmap so the bits are loaded a word at a time.
+    ///  The slice doesn't have to start at a byte boundary.
+    pub fn from_bit_slice(bits: &BitSlice<u8, Lsb0>) -> Self {
+        let mut chunks = bits.chunks(64);
+        Self::from_word_fn(bits.len(), |_| chunks.next().unwrap().load_le::<u64>())
+    }
+
+    /// Copies the bits into a [BitVec] of `self.num_bits()` bits, unused bits of the last byte are zeroed.
+    pub fn to_bit_vec(&self) -> BitVec<u8, Lsb0> {
+        let mut bits = BitVec::from_slice(self.as_byte_slice());
+        bits.truncate(self.num_bits());
+        bits.set_uninitialized(false);
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let bools = (0..1000).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+
+        for len in [0, 1, 7, 9, 64, 130, 1000] {
+            let bools = &bools[..len];
+            let bits = bools.iter().copied().collect::<BitVec<u8, Lsb0>>();
+
+            let bitmap = Bitmap::from_bit_slice(&bits);
+            assert_eq!(bitmap, Bitmap::from_bools(bools));
+            assert_eq!(bitmap.to_bit_vec(), bits);
+        }
+
+        // slices that don't start at a byte boundary
+        let bits = bools.iter().copied().collect::<BitVec<u8, Lsb0>>();
+        for start in [1, 3, 65] {
+            let bitmap = Bitmap::from_bit_slice(&bits[start..start + 130]);
+            assert_eq!(bitmap, Bitmap::from_bools(&bools[start..start + 130]));
+        }
+
+        // dirty padding isn't copied into the bit vec
+        let bitmap = Bitmap::ones(64).slice(0, 3);
+        assert_eq!(bitmap.to_bit_vec().len(), 3);
+        assert_eq!(bitmap.to_bit_vec().as_raw_slice(), &[0b111]);
+    }
+}
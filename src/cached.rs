@@ -0,0 +1,117 @@
+use std::cell::Cell;
+use std::ops::Deref;
+
+use crate::Bitmap;
+
+/// A [Bitmap] wrapper that memoizes [Bitmap::count_ones].
+///
+/// The cached count is invalidated by every mutating method of the wrapper. Mutating methods are
+///  only reachable through the wrapper, so the cache can't go stale. Cloning copies the cached value
+///  along with the bitmap, this is fine since mutating a clone copies the underlying buffer.
+#[derive(Clone)]
+pub struct CachedBitmap {
+    bitmap: Bitmap,
+    count_ones: Cell<Option<usize>>,
+}
+
+impl CachedBitmap {
+    /// Wraps the given bitmap, the count is computed lazily on first use.
+    pub fn new(bitmap: Bitmap) -> Self {
+        Self {
+            bitmap,
+            count_ones: Cell::new(None),
+        }
+    }
+
+    /// Number of set bits in the bitmap. Only computed after the first call or after a mutation.
+    pub fn count_ones(&self) -> usize {
+        match self.count_ones.get() {
+            Some(count) => count,
+            None => {
+                let count = self.bitmap.count_ones();
+                self.count_ones.set(Some(count));
+                count
+            }
+        }
+    }
+
+    /// See [Bitmap::set]
+    pub fn set(&mut self, bit_index: usize) {
+        self.bitmap.set(bit_index);
+        self.count_ones.set(None);
+    }
+
+    /// See [Bitmap::clear]
+    pub fn clear(&mut self, bit_index: usize) {
+        self.bitmap.clear(bit_index);
+        self.count_ones.set(None);
+    }
+
+    /// In place bitwise and, see [Bitmap]'s `BitAndAssign` implementation.
+    pub fn and_assign(&mut self, other: &Bitmap) {
+        self.bitmap &= other;
+        self.count_ones.set(None);
+    }
+
+    /// In place bitwise or, see [Bitmap]'s `BitOrAssign` implementation.
+    pub fn or_assign(&mut self, other: &Bitmap) {
+        self.bitmap |= other;
+        self.count_ones.set(None);
+    }
+
+    /// In place bitwise xor, see [Bitmap]'s `BitXorAssign` implementation.
+    pub fn xor_assign(&mut self, other: &Bitmap) {
+        self.bitmap ^= other;
+        self.count_ones.set(None);
+    }
+
+    /// Returns the wrapped bitmap
+    pub fn into_inner(self) -> Bitmap {
+        self.bitmap
+    }
+}
+
+impl From<Bitmap> for CachedBitmap {
+    fn from(bitmap: Bitmap) -> Self {
+        Self::new(bitmap)
+    }
+}
+
+impl Deref for CachedBitmap {
+    type Target = Bitmap;
+
+    fn deref(&self) -> &Bitmap {
+        &self.bitmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidation() {
+        let bools = (0..130).map(|i| i % 3 == 0).collect::<Vec<_>>();
+        let mut cached = CachedBitmap::new(Bitmap::from_bools(&bools));
+        assert_eq!(cached.count_ones(), 44);
+
+        let shared = cached.clone();
+
+        cached.set(1);
+        assert_eq!(cached.count_ones(), 45);
+        cached.clear(0);
+        assert_eq!(cached.count_ones(), 44);
+
+        // mutating doesn't leak into the clone
+        assert_eq!(shared.count_ones(), 44);
+        assert!(shared.get(0).unwrap());
+
+        let other = Bitmap::from_bools(&[true; 130]);
+        cached.or_assign(&other);
+        assert_eq!(cached.count_ones(), 130);
+        cached.xor_assign(&other);
+        assert_eq!(cached.count_ones(), 0);
+        cached.and_assign(&other);
+        assert_eq!(cached.count_ones(), cached.into_inner().count_ones());
+    }
+}
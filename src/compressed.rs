@@ -0,0 +1,377 @@
+use crate::bitmap::MAX_BITS;
+use crate::{Bitmap, DecodeError};
+
+const VERSION: u8 = 1;
+const ENCODING_DENSE: u8 = 0;
+const ENCODING_RLE: u8 = 1;
+const HEADER_LEN: usize = 10;
+/// Longest run written as a single varint, longer runs are split with zero length runs.
+///
+/// Bounds the number of bits a run length encoded body can claim per byte, so a few bytes of
+///  input can't make the decoder allocate a huge bitmap.
+const MAX_RUN_LEN: usize = 1 << 24;
+
+impl Bitmap {
+    /// Serializes the bitmap into a self describing format.
+    ///
+    /// The output starts with a header of the format version, an encoding tag and `num_bits` as a
+    ///  little endian `u64`. The header is followed by either the raw bytes of the bitmap or by the
+    ///  lengths of alternating runs as LEB128 varints, starting with a run of zeros. Runs longer
+    ///  than 2^24 bits are split by zero length runs. Run length encoding is only used if it
+    ///  produces a smaller output.
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let num_bits = self.num_bits();
+
+        let mut rle = Vec::new();
+        let mut expected = false;
+        for (value, _, len) in self.iter_runs() {
+            if value != expected {
+                write_varint(&mut rle, 0);
+            }
+            write_run(&mut rle, len);
+            expected = !value;
+        }
+
+        let num_bytes = num_bits.div_ceil(8);
+        let (encoding, body_len) = if rle.len() < num_bytes {
+            (ENCODING_RLE, rle.len())
+        } else {
+            (ENCODING_DENSE, num_bytes)
+        };
+
+        let mut out = Vec::with_capacity(HEADER_LEN + body_len);
+        out.push(VERSION);
+        out.push(encoding);
+        out.extend_from_slice(&(num_bits as u64).to_le_bytes());
+
+        if encoding == ENCODING_RLE {
+            out.extend_from_slice(&rle);
+        } else {
+            out.extend_from_slice(self.as_byte_slice());
+            if let Some(last) = out.last_mut().filter(|_| !num_bits.is_multiple_of(8)) {
+                *last &= (1 << (num_bits % 8)) - 1;
+            }
+        }
+
+        out
+    }
+
+    /// Deserializes a bitmap written by [Self::to_compressed_bytes].
+    pub fn from_compressed_bytes(bytes: &[u8]) -> Result<Bitmap, DecodeError> {
+        if bytes.len() < HEADER_LEN {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        if bytes[0] != VERSION {
+            return Err(DecodeError::UnsupportedVersion(bytes[0]));
+        }
+
+        let num_bits = u64::from_le_bytes(bytes[2..HEADER_LEN].try_into().unwrap());
+        let num_bits = usize::try_from(num_bits)
+            .ok()
+            .filter(|&num_bits| num_bits <= MAX_BITS)
+            .ok_or(DecodeError::TooLarge)?;
+        let body = &bytes[HEADER_LEN..];
+
+        match bytes[1] {
+            ENCODING_DENSE => {
+                let num_bytes = num_bits.div_ceil(8);
+                if body.len() < num_bytes {
+                    return Err(DecodeError::UnexpectedEnd);
+                }
+                if body.len() > num_bytes {
+                    return Err(DecodeError::TrailingBytes);
+                }
+
+                let mut bitmap = Bitmap::new(num_bits);
+                bitmap.as_mut_byte_slice().copy_from_slice(body);
+                bitmap.clear_padding_bits();
+
+                Ok(bitmap)
+            }
+            ENCODING_RLE => {
+                // Each run costs at least a byte and is at most MAX_RUN_LEN bits, reject headers
+                // the body can't add up to before allocating anything
+                if num_bits / MAX_RUN_LEN > body.len() {
+                    return Err(DecodeError::TooLarge);
+                }
+
+                let mut runs = Vec::new();
+                let mut total = 0usize;
+                let mut pos = 0;
+                while pos < body.len() {
+                    let len = read_varint(body, &mut pos)?;
+                    let len = usize::try_from(len)
+                        .ok()
+                        .filter(|&len| len <= MAX_RUN_LEN)
+                        .ok_or(DecodeError::TooLarge)?;
+                    total = total.checked_add(len).ok_or(DecodeError::TooLarge)?;
+                    runs.push(len);
+                }
+
+                if total != num_bits {
+                    return Err(DecodeError::LengthMismatch {
+                        expected: num_bits,
+                        actual: total,
+                    });
+                }
+
+                let mut bitmap = Bitmap::try_new(num_bits).map_err(|_| DecodeError::TooLarge)?;
+                let mut start = 0;
+                for (i, len) in runs.into_iter().enumerate() {
+                    if i % 2 == 1 {
+                        bitmap.set_range(start, len, true);
+                    }
+                    start += len;
+                }
+
+                Ok(bitmap)
+            }
+            tag => Err(DecodeError::UnknownEncoding(tag)),
+        }
+    }
+
+    /// Serializes the set bit indices as LEB128 varints of [Self::set_bit_deltas].
+    ///
+    /// The number of bits isn't included, it has to be passed to [Self::from_delta_varints]. This
+    ///  is smaller than the raw bytes for sparse bitmaps.
+    pub fn to_delta_varints(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for delta in self.set_bit_deltas() {
+            write_varint(&mut out, delta as u64);
+        }
+        out
+    }
+
+    /// Deserializes a bitmap of `num_bits` bits written by [Self::to_delta_varints].
+    ///
+    /// Zero deltas after the first index are accepted and set the same bit again.
+    pub fn from_delta_varints(bytes: &[u8], num_bits: usize) -> Result<Bitmap, DecodeError> {
+        let mut bitmap = Bitmap::new(num_bits);
+        let mut index = 0usize;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            let delta = read_varint(bytes, &mut pos)?;
+            let delta = usize::try_from(delta).map_err(|_| DecodeError::TooLarge)?;
+            index = index.checked_add(delta).ok_or(DecodeError::TooLarge)?;
+
+            if index >= num_bits {
+                return Err(DecodeError::IndexOutOfBounds { index, num_bits });
+            }
+            bitmap.set(index);
+        }
+
+        Ok(bitmap)
+    }
+}
+
+/// Appends a run of `len` bits to `out`, split into runs of at most [MAX_RUN_LEN] bits that are
+///  separated by zero length runs of the other value.
+fn write_run(out: &mut Vec<u8>, mut len: usize) {
+    while len > MAX_RUN_LEN {
+        write_varint(out, MAX_RUN_LEN as u64);
+        write_varint(out, 0);
+        len -= MAX_RUN_LEN;
+    }
+    write_varint(out, len as u64);
+}
+
+/// Appends `value` to `out` as a LEB128 varint
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a LEB128 varint from `bytes` starting at `pos`, advancing `pos` past it
+pub(crate) fn read_varint(bytes: &[u8], pos: &mut usize) -> Result<u64, DecodeError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *bytes.get(*pos).ok_or(DecodeError::UnexpectedEnd)?;
+        *pos += 1;
+
+        if shift == 63 && byte > 1 {
+            return Err(DecodeError::InvalidVarint);
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+
+        shift += 7;
+        if shift > 63 {
+            return Err(DecodeError::InvalidVarint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut sparse = Bitmap::new(10_000);
+        sparse.set_range(100, 3000, true);
+        sparse.set(7000);
+        let dense = Bitmap::from_fn(1001, |i| (i * 7919) % 13 < 6);
+
+        for bitmap in [
+            sparse,
+            dense,
+            Bitmap::new(0),
+            Bitmap::ones(70),
+            Bitmap::ones(200).slice(0, 13),
+        ] {
+            let bytes = bitmap.to_compressed_bytes();
+            assert!(bytes.len() <= HEADER_LEN + bitmap.num_bits().div_ceil(8));
+            let decoded = Bitmap::from_compressed_bytes(&bytes).unwrap();
+            assert_eq!(decoded, bitmap);
+            assert_eq!(decoded.validate(), Ok(()));
+        }
+
+        let mut sparse = Bitmap::new(10_000);
+        sparse.set(5);
+        assert_eq!(sparse.to_compressed_bytes()[1], ENCODING_RLE);
+
+        let mut long_runs = Bitmap::new(3 * MAX_RUN_LEN + 5);
+        long_runs.set_range(7, 2 * MAX_RUN_LEN + 1, true);
+        let bytes = long_runs.to_compressed_bytes();
+        assert_eq!(bytes[1], ENCODING_RLE);
+        assert_eq!(Bitmap::from_compressed_bytes(&bytes), Ok(long_runs));
+
+        assert_eq!(
+            Bitmap::from_fn(1001, |i| i % 2 == 1).to_compressed_bytes()[1],
+            ENCODING_DENSE
+        );
+    }
+
+    #[test]
+    fn test_delta_varints() {
+        let mut sparse = Bitmap::new(100_000);
+        for i in [0, 3, 130, 200, 50_000, 99_999] {
+            sparse.set(i);
+        }
+        let bytes = sparse.to_delta_varints();
+        assert!(bytes.len() < sparse.num_bits().div_ceil(8));
+        assert_eq!(Bitmap::from_delta_varints(&bytes, 100_000), Ok(sparse));
+
+        let dense = Bitmap::from_fn(1000, |i| (i * 7919) % 13 < 6);
+        assert_eq!(
+            Bitmap::from_delta_varints(&dense.to_delta_varints(), 1000),
+            Ok(dense)
+        );
+
+        assert!(Bitmap::new(0).to_delta_varints().is_empty());
+        assert_eq!(Bitmap::from_delta_varints(&[], 0), Ok(Bitmap::new(0)));
+        assert_eq!(Bitmap::from_delta_varints(&[], 10), Ok(Bitmap::new(10)));
+    }
+
+    #[test]
+    fn test_delta_varints_malformed() {
+        assert_eq!(
+            Bitmap::from_delta_varints(&[3, 7], 10),
+            Err(DecodeError::IndexOutOfBounds {
+                index: 10,
+                num_bits: 10
+            })
+        );
+        assert_eq!(
+            Bitmap::from_delta_varints(&[0], 0),
+            Err(DecodeError::IndexOutOfBounds {
+                index: 0,
+                num_bits: 0
+            })
+        );
+        assert_eq!(
+            Bitmap::from_delta_varints(&[3, 0x80], 10),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Bitmap::from_delta_varints(&[0xff; 11], 10),
+            Err(DecodeError::InvalidVarint)
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let bytes = Bitmap::from_fn(100, |i| i % 3 == 0).to_compressed_bytes();
+
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&bytes[..5]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+
+        let mut version = bytes.clone();
+        version[0] = 9;
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&version),
+            Err(DecodeError::UnsupportedVersion(9))
+        );
+
+        let mut encoding = bytes.clone();
+        encoding[1] = 7;
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&encoding),
+            Err(DecodeError::UnknownEncoding(7))
+        );
+
+        let mut rle = vec![VERSION, ENCODING_RLE];
+        rle.extend_from_slice(&10u64.to_le_bytes());
+        rle.extend_from_slice(&[3, 4]);
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&rle),
+            Err(DecodeError::LengthMismatch {
+                expected: 10,
+                actual: 7
+            })
+        );
+        rle.push(0x80);
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&rle),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        rle.pop();
+        rle.extend_from_slice(&[0xff; 11]);
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&rle),
+            Err(DecodeError::InvalidVarint)
+        );
+
+        for num_bits in [1u64 << 63, 1 << 62, MAX_BITS as u64, MAX_RUN_LEN as u64 + 1] {
+            let mut huge = vec![VERSION, ENCODING_RLE];
+            huge.extend_from_slice(&num_bits.to_le_bytes());
+            write_varint(&mut huge, num_bits);
+            assert_eq!(
+                Bitmap::from_compressed_bytes(&huge),
+                Err(DecodeError::TooLarge)
+            );
+        }
+
+        // Every run fits the limit, but the header claims more than the body can add up to
+        let mut short_body = vec![VERSION, ENCODING_RLE];
+        short_body.extend_from_slice(&(100 * MAX_RUN_LEN as u64).to_le_bytes());
+        write_run(&mut short_body, 2 * MAX_RUN_LEN);
+        assert_eq!(
+            Bitmap::from_compressed_bytes(&short_body),
+            Err(DecodeError::TooLarge)
+        );
+    }
+}
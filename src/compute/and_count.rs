@@ -0,0 +1,172 @@
+/// Returns the number of set bits in the bitwise and of the `num_words` words starting at `a` and `b`.
+///
+/// Uses AVX2 if it is available at runtime.
+///
+/// # Safety
+///
+/// Both `a` and `b` must have at least `num_words` words.
+pub unsafe fn and_count(a: *const u64, b: *const u64, num_words: usize) -> usize {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("avx2") {
+        return and_count_avx2(a, b, num_words);
+    }
+
+    and_count_scalar(a, b, num_words)
+}
+
+/// # Safety
+///
+/// Both `a` and `b` must have at least `num_words` words.
+pub unsafe fn and_count_scalar(a: *const u64, b: *const u64, num_words: usize) -> usize {
+    let mut count = 0;
+    for i in 0..num_words {
+        count += (a.add(i).read_unaligned() & b.add(i).read_unaligned()).count_ones() as usize;
+    }
+    count
+}
+
+/// Harley-Seal popcount over the and of 256 bit vectors, see "Faster Population Counts Using AVX2
+///  Instructions" by Muła, Kurz and Lemire.
+///
+/// # Safety
+///
+/// Both `a` and `b` must have at least `num_words` words. The CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn and_count_avx2(a: *const u64, b: *const u64, num_words: usize) -> usize {
+    use core::arch::x86_64::{
+        __m256i, _mm256_add_epi64, _mm256_and_si256, _mm256_loadu_si256, _mm256_or_si256,
+        _mm256_setzero_si256, _mm256_slli_epi64, _mm256_storeu_si256, _mm256_xor_si256,
+    };
+
+    const WORDS_PER_VEC: usize = 4;
+    const BLOCK_VECS: usize = 16;
+
+    #[inline(always)]
+    unsafe fn csa(a: __m256i, b: __m256i, c: __m256i) -> (__m256i, __m256i) {
+        let u = _mm256_xor_si256(a, b);
+        let high = _mm256_or_si256(_mm256_and_si256(a, b), _mm256_and_si256(u, c));
+        (high, _mm256_xor_si256(u, c))
+    }
+
+    let num_vecs = num_words / WORDS_PER_VEC;
+    let load = |i: usize| {
+        let a_vec = _mm256_loadu_si256(a.add(i * WORDS_PER_VEC) as *const __m256i);
+        let b_vec = _mm256_loadu_si256(b.add(i * WORDS_PER_VEC) as *const __m256i);
+        _mm256_and_si256(a_vec, b_vec)
+    };
+
+    let mut total = _mm256_setzero_si256();
+    let mut ones = _mm256_setzero_si256();
+    let mut twos = _mm256_setzero_si256();
+    let mut fours = _mm256_setzero_si256();
+    let mut eights = _mm256_setzero_si256();
+
+    let mut i = 0;
+    while i + BLOCK_VECS <= num_vecs {
+        let (twos_a, o) = csa(ones, load(i), load(i + 1));
+        let (twos_b, o) = csa(o, load(i + 2), load(i + 3));
+        let (fours_a, t) = csa(twos, twos_a, twos_b);
+        let (twos_a, o) = csa(o, load(i + 4), load(i + 5));
+        let (twos_b, o) = csa(o, load(i + 6), load(i + 7));
+        let (fours_b, t) = csa(t, twos_a, twos_b);
+        let (eights_a, f) = csa(fours, fours_a, fours_b);
+        let (twos_a, o) = csa(o, load(i + 8), load(i + 9));
+        let (twos_b, o) = csa(o, load(i + 10), load(i + 11));
+        let (fours_a, t) = csa(t, twos_a, twos_b);
+        let (twos_a, o) = csa(o, load(i + 12), load(i + 13));
+        let (twos_b, o) = csa(o, load(i + 14), load(i + 15));
+        let (fours_b, t) = csa(t, twos_a, twos_b);
+        let (eights_b, f) = csa(f, fours_a, fours_b);
+        let (sixteens, e) = csa(eights, eights_a, eights_b);
+
+        total = _mm256_add_epi64(total, popcount_avx2(sixteens));
+        ones = o;
+        twos = t;
+        fours = f;
+        eights = e;
+        i += BLOCK_VECS;
+    }
+
+    total = _mm256_slli_epi64(total, 4);
+    total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount_avx2(eights), 3));
+    total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount_avx2(fours), 2));
+    total = _mm256_add_epi64(total, _mm256_slli_epi64(popcount_avx2(twos), 1));
+    total = _mm256_add_epi64(total, popcount_avx2(ones));
+
+    while i < num_vecs {
+        total = _mm256_add_epi64(total, popcount_avx2(load(i)));
+        i += 1;
+    }
+
+    let mut lanes = [0u64; 4];
+    _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, total);
+    let vec_count = lanes.iter().sum::<u64>() as usize;
+
+    let done = num_vecs * WORDS_PER_VEC;
+    vec_count + and_count_scalar(a.add(done), b.add(done), num_words - done)
+}
+
+/// Popcount of each 64 bit lane using a nibble lookup table.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn popcount_avx2(v: core::arch::x86_64::__m256i) -> core::arch::x86_64::__m256i {
+    use core::arch::x86_64::{
+        _mm256_add_epi8, _mm256_and_si256, _mm256_sad_epu8, _mm256_set1_epi8, _mm256_setr_epi8,
+        _mm256_setzero_si256, _mm256_shuffle_epi8, _mm256_srli_epi16,
+    };
+
+    let lookup = _mm256_setr_epi8(
+        0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3, 3, 4, 0, 1, 1, 2, 1, 2, 2, 3, 1, 2, 2, 3, 2, 3,
+        3, 4,
+    );
+    let low_mask = _mm256_set1_epi8(0x0f);
+
+    let low = _mm256_and_si256(v, low_mask);
+    let high = _mm256_and_si256(_mm256_srli_epi16(v, 4), low_mask);
+    let counts = _mm256_add_epi8(
+        _mm256_shuffle_epi8(lookup, low),
+        _mm256_shuffle_epi8(lookup, high),
+    );
+
+    _mm256_sad_epu8(counts, _mm256_setzero_si256())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_and_count_agree() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let a = (0..1000).map(|_| rng.gen::<u64>()).collect::<Vec<_>>();
+        let b = (0..1000).map(|_| rng.gen::<u64>()).collect::<Vec<_>>();
+
+        for num_words in [0usize, 1, 3, 4, 5, 63, 64, 65, 100, 128, 1000] {
+            let expected = a[..num_words]
+                .iter()
+                .zip(b[..num_words].iter())
+                .map(|(a, b)| (a & b).count_ones() as usize)
+                .sum::<usize>();
+
+            unsafe {
+                assert_eq!(
+                    and_count_scalar(a.as_ptr(), b.as_ptr(), num_words),
+                    expected
+                );
+                assert_eq!(and_count(a.as_ptr(), b.as_ptr(), num_words), expected);
+                #[cfg(target_arch = "x86_64")]
+                if std::is_x86_feature_detected!("avx2") {
+                    assert_eq!(and_count_avx2(a.as_ptr(), b.as_ptr(), num_words), expected);
+                    let ones = vec![u64::MAX; num_words];
+                    assert_eq!(
+                        and_count_avx2(ones.as_ptr(), ones.as_ptr(), num_words),
+                        num_words * 64
+                    );
+                }
+            }
+        }
+    }
+}
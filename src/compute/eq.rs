@@ -0,0 +1,92 @@
+/// Returns if the `len` bytes starting at `a` and `b` are equal.
+///
+/// Uses AVX2 if it is available at runtime.
+///
+/// # Safety
+///
+/// Both `a` and `b` must have at least `len` size.
+pub unsafe fn eq(a: *const u8, b: *const u8, len: usize) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    if std::is_x86_feature_detected!("avx2") {
+        return eq_avx2(a, b, len);
+    }
+
+    eq_scalar(a, b, len)
+}
+
+/// # Safety
+///
+/// Both `a` and `b` must have at least `len` size.
+pub unsafe fn eq_scalar(mut a: *const u8, mut b: *const u8, len: usize) -> bool {
+    for _ in 0..len / 8 {
+        if (a as *const u64).read_unaligned() != (b as *const u64).read_unaligned() {
+            return false;
+        }
+        a = a.add(8);
+        b = b.add(8);
+    }
+
+    for _ in 0..len % 8 {
+        if *a != *b {
+            return false;
+        }
+        a = a.add(1);
+        b = b.add(1);
+    }
+
+    true
+}
+
+/// # Safety
+///
+/// Both `a` and `b` must have at least `len` size. The CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn eq_avx2(mut a: *const u8, mut b: *const u8, len: usize) -> bool {
+    use core::arch::x86_64::{
+        __m256i, _mm256_cmpeq_epi8, _mm256_loadu_si256, _mm256_movemask_epi8,
+    };
+
+    const STEP: usize = 32;
+
+    for _ in 0..len / STEP {
+        let a_vec = _mm256_loadu_si256(a as *const __m256i);
+        let b_vec = _mm256_loadu_si256(b as *const __m256i);
+        if _mm256_movemask_epi8(_mm256_cmpeq_epi8(a_vec, b_vec)) != -1 {
+            return false;
+        }
+        a = a.add(STEP);
+        b = b.add(STEP);
+    }
+
+    eq_scalar(a, b, len % STEP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eq_agree() {
+        let a = (0..1000).map(|i| (i * 7 % 251) as u8).collect::<Vec<_>>();
+
+        for len in [0usize, 1, 7, 8, 31, 32, 33, 64, 100, 1000] {
+            for diff_at in [None, Some(0), Some(len / 2), Some(len.saturating_sub(1))] {
+                let mut b = a.clone();
+                if let Some(pos) = diff_at.filter(|_| len > 0) {
+                    b[pos] ^= 1 << (pos % 8);
+                }
+                let expected = a[..len] == b[..len];
+
+                unsafe {
+                    assert_eq!(eq_scalar(a.as_ptr(), b.as_ptr(), len), expected);
+                    assert_eq!(eq(a.as_ptr(), b.as_ptr(), len), expected);
+                    #[cfg(target_arch = "x86_64")]
+                    if std::is_x86_feature_detected!("avx2") {
+                        assert_eq!(eq_avx2(a.as_ptr(), b.as_ptr(), len), expected);
+                    }
+                }
+            }
+        }
+    }
+}
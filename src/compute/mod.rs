@@ -1,5 +1,9 @@
+mod and_count;
+mod eq;
 mod re_align;
 mod set_ranges;
 
+pub use and_count::and_count;
+pub use eq::eq;
 pub use re_align::re_align;
-pub use set_ranges::set_ranges;
+pub use set_ranges::push_set_ranges;
@@ -1,3 +1,48 @@
-pub unsafe fn set_ranges(_bitmap: *const u8, _len: usize) -> Vec<(usize, usize)> {
-    todo!()
+/// Appends the `(start, len)` ranges of set bits in the first `num_bits` bits of `words` to `out`.
+///
+/// Bits past `num_bits` are ignored. `words` should yield at least `num_bits.div_ceil(64)` words.
+pub fn push_set_ranges(
+    words: impl Iterator<Item = u64>,
+    num_bits: usize,
+    out: &mut Vec<(usize, usize)>,
+) {
+    let mut run_start = None;
+
+    let num_words = num_bits.div_ceil(64);
+
+    for (word_index, mut word) in words.take(num_words).enumerate() {
+        if word_index + 1 == num_words && !num_bits.is_multiple_of(64) {
+            word &= (1 << (num_bits % 64)) - 1;
+        }
+
+        let base = word_index * 64;
+        let mut pos = 0;
+
+        while pos < 64 {
+            let rest = word >> pos;
+            match run_start {
+                Some(start) => {
+                    let ones = rest.trailing_ones();
+                    if pos + ones >= 64 {
+                        break;
+                    }
+                    out.push((start, base + (pos + ones) as usize - start));
+                    run_start = None;
+                    pos += ones;
+                }
+                None => {
+                    if rest == 0 {
+                        break;
+                    }
+                    let zeros = rest.trailing_zeros();
+                    run_start = Some(base + (pos + zeros) as usize);
+                    pos += zeros;
+                }
+            }
+        }
+    }
+
+    if let Some(start) = run_start {
+        out.push((start, num_bits - start));
+    }
 }
@@ -0,0 +1,153 @@
+use std::fmt;
+
+/// Error returned by [crate::Bitmap::validate]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The buffer can't hold the number of bits of the bitmap
+    BufferTooSmall { num_bits: usize, buffer_len: usize },
+    /// Some bits past the end of the bitmap are set
+    DirtyPadding { num_padding_bits: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall {
+                num_bits,
+                buffer_len,
+            } => write!(
+                f,
+                "buffer of {} bytes can't hold {} bits",
+                buffer_len, num_bits
+            ),
+            Self::DirtyPadding { num_padding_bits } => {
+                write!(f, "{} padding bits are set", num_padding_bits)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Error returned by the fallible constructors of [crate::Bitmap]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// The buffer needed to hold the bits is too large to be allocated
+    CapacityOverflow { num_bits: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CapacityOverflow { num_bits } => {
+                write!(
+                    f,
+                    "buffer for {} bits exceeds the maximum capacity",
+                    num_bits
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Error returned by [crate::validate_ranges]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeError {
+    /// The range at `index` has zero length
+    Empty { index: usize },
+    /// The range at `index` ends past the end of the bitmap
+    OutOfBounds { index: usize, num_bits: usize },
+    /// The range at `index` starts before the previous range
+    Unsorted { index: usize },
+    /// The range at `index` starts before the previous range ends
+    Overlapping { index: usize },
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty { index } => write!(f, "range {} is empty", index),
+            Self::OutOfBounds { index, num_bits } => write!(
+                f,
+                "range {} is out of bounds for bitmap of {} bits",
+                index, num_bits
+            ),
+            Self::Unsorted { index } => {
+                write!(f, "range {} starts before the previous range", index)
+            }
+            Self::Overlapping { index } => write!(f, "range {} overlaps the previous range", index),
+        }
+    }
+}
+
+impl std::error::Error for RangeError {}
+
+/// Error returned when a bit index is outside of a bitmap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+    /// The requested index
+    pub index: usize,
+    /// Number of bits in the bitmap
+    pub num_bits: usize,
+}
+
+impl fmt::Display for OutOfBounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index {} is out of bounds for bitmap of {} bits",
+            self.index, self.num_bits
+        )
+    }
+}
+
+impl std::error::Error for OutOfBounds {}
+
+/// Error returned when decoding a serialized bitmap fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Input ended before the bitmap was fully decoded
+    UnexpectedEnd,
+    /// Input has bytes left after the bitmap was fully decoded
+    TrailingBytes,
+    /// Format version in the header isn't supported
+    UnsupportedVersion(u8),
+    /// Encoding tag in the header isn't known
+    UnknownEncoding(u8),
+    /// A varint doesn't fit in a `u64`
+    InvalidVarint,
+    /// Decoded number of bits doesn't match the number of bits in the header
+    LengthMismatch { expected: usize, actual: usize },
+    /// Number of bits in the header is over the maximum size of a bitmap
+    TooLarge,
+    /// A decoded bit index is outside of the bitmap
+    IndexOutOfBounds { index: usize, num_bits: usize },
+    /// Input isn't valid base64
+    InvalidBase64,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Self::TrailingBytes => write!(f, "unexpected bytes after the end of the bitmap"),
+            Self::UnsupportedVersion(version) => write!(f, "unsupported version {}", version),
+            Self::UnknownEncoding(tag) => write!(f, "unknown encoding {}", tag),
+            Self::InvalidVarint => write!(f, "varint overflows u64"),
+            Self::LengthMismatch { expected, actual } => {
+                write!(f, "expected {} bits but decoded {}", expected, actual)
+            }
+            Self::TooLarge => write!(f, "number of bits is too large"),
+            Self::IndexOutOfBounds { index, num_bits } => write!(
+                f,
+                "decoded index {} is out of bounds for bitmap of {} bits",
+                index, num_bits
+            ),
+            Self::InvalidBase64 => write!(f, "invalid base64"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
@@ -1,4 +1,23 @@
+#[cfg(feature = "base64")]
+mod base64_interop;
 mod bitmap;
+mod bitmap_ref;
+#[cfg(feature = "bitvec")]
+mod bitvec_interop;
+mod cached;
+mod compressed;
 mod compute;
+mod error;
+mod prefix_popcount;
+mod ranges;
+mod rle;
+mod stored;
 
 pub use bitmap::Bitmap;
+pub use bitmap_ref::BitmapRef;
+pub use cached::CachedBitmap;
+pub use error::{BuildError, DecodeError, OutOfBounds, RangeError, ValidationError};
+pub use prefix_popcount::PrefixPopcount;
+pub use ranges::validate_ranges;
+pub use rle::RleBitmap;
+pub use stored::StoredBitmap;
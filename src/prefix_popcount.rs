@@ -0,0 +1,49 @@
+use crate::Bitmap;
+
+/// Cumulative popcounts at word boundaries of a [Bitmap], answers range count queries in constant time.
+///
+/// Holds a shared reference to the bitmap's buffer, building it doesn't copy the bitmap.
+#[derive(Clone)]
+pub struct PrefixPopcount {
+    bitmap: Bitmap,
+    counts: Vec<usize>,
+}
+
+impl PrefixPopcount {
+    /// Builds the prefix counts of the given bitmap, see [Bitmap::prefix_counts]
+    pub fn new(bitmap: Bitmap) -> Self {
+        let counts = bitmap.prefix_counts();
+        Self { bitmap, counts }
+    }
+
+    /// Number of set bits in `[start, end)`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `start > end` or if `end > bitmap.num_bits()`.
+    pub fn range_count(&self, start: usize, end: usize) -> usize {
+        assert!(start <= end);
+        assert!(end <= self.bitmap.num_bits());
+
+        self.rank(end) - self.rank(start)
+    }
+
+    /// Number of set bits in `[0, bit_index)`
+    fn rank(&self, bit_index: usize) -> usize {
+        let word_index = bit_index / 64;
+        let rem = bit_index % 64;
+
+        let partial = if rem == 0 {
+            0
+        } else {
+            (self.bitmap.word(word_index) & ((1 << rem) - 1)).count_ones() as usize
+        };
+
+        self.counts[word_index] + partial
+    }
+
+    /// Cumulative counts at each word boundary
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+}
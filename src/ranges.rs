@@ -0,0 +1,77 @@
+use crate::RangeError;
+
+/// Checks that `ranges` is a well formed list of `(start, len)` set ranges for a bitmap of
+///  `num_bits` bits, like the output of [crate::Bitmap::set_ranges].
+///
+/// Ranges have to be non-empty, inside of the bitmap and sorted by start without overlapping.
+///  Adjacent ranges are accepted. The error refers to the first range that breaks one of these.
+pub fn validate_ranges(ranges: &[(usize, usize)], num_bits: usize) -> Result<(), RangeError> {
+    let mut prev_end = None;
+
+    for (index, &(start, len)) in ranges.iter().enumerate() {
+        if len == 0 {
+            return Err(RangeError::Empty { index });
+        }
+
+        let end = match start.checked_add(len) {
+            Some(end) if end <= num_bits => end,
+            _ => return Err(RangeError::OutOfBounds { index, num_bits }),
+        };
+
+        if let Some(prev_end) = prev_end {
+            let prev_start = ranges[index - 1].0;
+            if start < prev_start {
+                return Err(RangeError::Unsorted { index });
+            }
+            if start < prev_end {
+                return Err(RangeError::Overlapping { index });
+            }
+        }
+
+        prev_end = Some(end);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bitmap;
+
+    #[test]
+    fn test_validate_ranges() {
+        assert_eq!(validate_ranges(&[], 0), Ok(()));
+        assert_eq!(validate_ranges(&[(0, 10), (10, 5), (20, 80)], 100), Ok(()));
+
+        let bitmap = Bitmap::from_fn(1000, |i| (i * 7919) % 13 < 6);
+        assert_eq!(validate_ranges(&bitmap.set_ranges(), 1000), Ok(()));
+
+        assert_eq!(
+            validate_ranges(&[(0, 10), (5, 10)], 100),
+            Err(RangeError::Overlapping { index: 1 })
+        );
+        assert_eq!(
+            validate_ranges(&[(20, 10), (5, 10)], 100),
+            Err(RangeError::Unsorted { index: 1 })
+        );
+        assert_eq!(
+            validate_ranges(&[(0, 10), (95, 10)], 100),
+            Err(RangeError::OutOfBounds {
+                index: 1,
+                num_bits: 100
+            })
+        );
+        assert_eq!(
+            validate_ranges(&[(usize::MAX, 2)], 100),
+            Err(RangeError::OutOfBounds {
+                index: 0,
+                num_bits: 100
+            })
+        );
+        assert_eq!(
+            validate_ranges(&[(0, 10), (50, 0)], 100),
+            Err(RangeError::Empty { index: 1 })
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::Bitmap;
+
+/// A run length encoded bitmap, stores the `(start, len)` ranges of set bits.
+///
+/// Takes space proportional to the number of set runs instead of the number of bits, so it is
+///  much smaller than a [Bitmap] when the bits form a few long runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RleBitmap {
+    ranges: Vec<(usize, usize)>,
+    num_bits: usize,
+}
+
+impl RleBitmap {
+    /// Encodes the given bitmap, see [Bitmap::set_ranges]
+    pub fn from_bitmap(bitmap: &Bitmap) -> Self {
+        Self {
+            ranges: bitmap.set_ranges(),
+            num_bits: bitmap.num_bits(),
+        }
+    }
+
+    /// Number of bits in this bitmap
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    /// Sorted, non-overlapping and non-adjacent `(start, len)` ranges of set bits
+    pub fn ranges(&self) -> &[(usize, usize)] {
+        &self.ranges
+    }
+
+    /// Returns the bit at given index, `None` if it is out of bounds.
+    ///
+    /// Binary searches the ranges.
+    pub fn get(&self, bit_index: usize) -> Option<bool> {
+        if bit_index >= self.num_bits {
+            return None;
+        }
+
+        let pos = self
+            .ranges
+            .partition_point(|&(start, _)| start <= bit_index);
+        Some(
+            pos > 0 && {
+                let (start, len) = self.ranges[pos - 1];
+                bit_index < start + len
+            },
+        )
+    }
+
+    /// Number of set bits in the bitmap
+    pub fn count_ones(&self) -> usize {
+        self.ranges.iter().map(|&(_, len)| len).sum()
+    }
+
+    /// Decodes into a [Bitmap]
+    pub fn to_bitmap(&self) -> Bitmap {
+        let mut bitmap = Bitmap::new(self.num_bits);
+        for &(start, len) in self.ranges.iter() {
+            bitmap.set_range(start, len, true);
+        }
+        bitmap
+    }
+
+    /// Approximate number of bytes used to hold the ranges
+    pub fn size_bytes(&self) -> usize {
+        self.ranges.len() * std::mem::size_of::<(usize, usize)>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut bitmap = Bitmap::new(300);
+        bitmap.set_range(0, 5, true);
+        bitmap.set_range(63, 2, true);
+        bitmap.set_range(200, 100, true);
+
+        let rle = RleBitmap::from_bitmap(&bitmap);
+        assert_eq!(rle.ranges(), &[(0, 5), (63, 2), (200, 100)]);
+        assert_eq!(rle.to_bitmap(), bitmap);
+        assert_eq!(rle.count_ones(), bitmap.count_ones());
+        for i in 0..=300 {
+            assert_eq!(rle.get(i), bitmap.get(i));
+        }
+
+        let rle = RleBitmap::from_bitmap(&Bitmap::new(0));
+        assert_eq!(rle.to_bitmap(), Bitmap::new(0));
+        assert_eq!(rle.get(0), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{Bitmap, RleBitmap};
+
+/// A bitmap stored in whichever of the dense and run length encoded representations is smaller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredBitmap {
+    /// One bit per bit, see [Bitmap]
+    Dense(Bitmap),
+    /// Ranges of set bits, see [RleBitmap]
+    Rle(RleBitmap),
+}
+
+impl StoredBitmap {
+    /// Picks the smaller representation for the given bitmap.
+    ///
+    /// Compares [Bitmap::num_bytes] to [RleBitmap::size_bytes], the dense one is kept on ties since
+    ///  reads from it are cheaper.
+    pub fn from_bitmap(bitmap: Bitmap) -> Self {
+        let rle = RleBitmap::from_bitmap(&bitmap);
+        if rle.size_bytes() < bitmap.num_bytes() {
+            Self::Rle(rle)
+        } else {
+            Self::Dense(bitmap)
+        }
+    }
+
+    /// Number of bits in this bitmap
+    pub fn num_bits(&self) -> usize {
+        match self {
+            Self::Dense(bitmap) => bitmap.num_bits(),
+            Self::Rle(rle) => rle.num_bits(),
+        }
+    }
+
+    /// Returns the bit at given index, `None` if it is out of bounds.
+    pub fn get(&self, bit_index: usize) -> Option<bool> {
+        match self {
+            Self::Dense(bitmap) => bitmap.get(bit_index),
+            Self::Rle(rle) => rle.get(bit_index),
+        }
+    }
+
+    /// Number of set bits in the bitmap
+    pub fn count_ones(&self) -> usize {
+        match self {
+            Self::Dense(bitmap) => bitmap.count_ones(),
+            Self::Rle(rle) => rle.count_ones(),
+        }
+    }
+
+    /// Returns the bits as a dense [Bitmap], doesn't copy if it is already stored as one.
+    pub fn to_bitmap(&self) -> Bitmap {
+        match self {
+            Self::Dense(bitmap) => bitmap.clone(),
+            Self::Rle(rle) => rle.to_bitmap(),
+        }
+    }
+}
+
+impl From<Bitmap> for StoredBitmap {
+    fn from(bitmap: Bitmap) -> Self {
+        Self::from_bitmap(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn test_picks_smaller() {
+        let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(0);
+        let bools = (0..10_000).map(|_| rng.gen::<bool>()).collect::<Vec<_>>();
+        let dense = Bitmap::from_bools(&bools);
+
+        let stored = StoredBitmap::from_bitmap(dense.clone());
+        assert!(matches!(stored, StoredBitmap::Dense(_)));
+        assert_eq!(stored.to_bitmap(), dense);
+        assert_eq!(stored.count_ones(), dense.count_ones());
+
+        let mut runs = Bitmap::new(10_000);
+        runs.set_range(100, 4000, true);
+        runs.set_range(6000, 10, true);
+
+        let stored = StoredBitmap::from_bitmap(runs.clone());
+        assert!(matches!(stored, StoredBitmap::Rle(_)));
+        assert_eq!(stored.num_bits(), 10_000);
+        assert_eq!(stored.to_bitmap(), runs);
+        assert_eq!(stored.count_ones(), 4010);
+        for i in [0, 99, 100, 4099, 4100, 6005, 9999, 10_000] {
+            assert_eq!(stored.get(i), runs.get(i));
+        }
+    }
+}